@@ -0,0 +1,320 @@
+use clap::Parser;
+use http::{HeaderName, HeaderValue, Method, Uri};
+use hyper::body::Bytes;
+use std::path::PathBuf;
+
+use crate::proxy::Proxy;
+use crate::secrets::Secrets;
+use crate::spec::{Item, RevealSpec, Rules, SENSITIVE_REQUEST_HEADERS, SENSITIVE_RESPONSE_HEADERS};
+
+// Defaults of the demo. SERVER_URL's domain must be the same as SERVER_DOMAIN on the verifier side.
+const SERVER_URL: &str = "https://raw.githubusercontent.com/tlsnotary/tlsn/refs/tags/v0.1.0-alpha.12/crates/server-fixture/server/src/data/1kb.json";
+const VERIFIER_URL: &str = "http://localhost:9816/verify";
+const SECRET_HEADER: &str = "Secret: {{secret:DEMO_SECRET}}";
+const DEMO_SECRET: &str = "TLSNotary's private key 🤡";
+const DEMO_REVEAL_SPEC: &str = include_str!("../reveal.json");
+
+/// Proves an HTTPS exchange with a server to a TLSNotary verifier.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Args {
+    /// URL of the server; its domain must be the one the verifier expects
+    #[arg(default_value = SERVER_URL)]
+    pub url: String,
+
+    /// Request method
+    #[arg(short = 'X', long, default_value = "GET")]
+    pub method: Method,
+
+    /// Request header as `Name: value`, may be repeated. Replaces the default `Host`,
+    /// `Connection` and `Accept-Encoding` headers of the same name. Without any, requests to the
+    /// demo URL carry a `Secret: {{secret:DEMO_SECRET}}` header
+    #[arg(short = 'H', long = "header", value_name = "HEADER", value_parser = parse_header)]
+    pub headers: Vec<(HeaderName, HeaderValue)>,
+
+    /// Request body, sent with a `Content-Length` header
+    #[arg(short, long, conflicts_with = "data_file")]
+    pub data: Option<String>,
+
+    /// Reads the request body from a file
+    #[arg(long, value_name = "PATH")]
+    pub data_file: Option<PathBuf>,
+
+    /// Text hidden from the verifier wherever it occurs in the requests, may be repeated
+    #[arg(long, value_name = "TEXT")]
+    pub redact: Vec<String>,
+
+    /// File of `NAME=value` lines resolving `{{secret:NAME}}` placeholders in the URL, headers,
+    /// bodies and prelude paths, before environment variables are looked up. Resolved secrets
+    /// are never revealed
+    #[arg(long, value_name = "PATH")]
+    pub secrets_file: Option<PathBuf>,
+
+    /// Header whose value is hidden from the verifier, in addition to Authorization, Cookie,
+    /// X-API-Key and Proxy-Authorization in requests and Set-Cookie in responses; may be repeated
+    #[arg(long, value_name = "NAME")]
+    pub sensitive_header: Vec<String>,
+
+    /// Sensitive header whose value may be revealed nonetheless, may be repeated
+    #[arg(long, value_name = "NAME")]
+    pub reveal_header: Vec<String>,
+
+    /// Request sent before URL over the same keep-alive connection, e.g. to log in first, as
+    /// `[METHOD] PATH [@FILE]`: GET by default, with the body read from FILE if given; may be
+    /// repeated
+    #[arg(long, value_name = "REQUEST", value_parser = parse_prelude)]
+    pub prelude: Vec<Prelude>,
+
+    /// URL of the verifier: `http://` connects over raw TCP (`Upgrade: tlsn`), `ws://` over
+    /// WebSocket
+    #[arg(long, default_value = VERIFIER_URL, value_parser = parse_verifier_url)]
+    pub verifier: Uri,
+
+    /// Reach the server through a WebSocket-to-TCP proxy, as the browser prover does, e.g.
+    /// `wss://notary.pse.dev/proxy`. The server's host is passed in the `token` query parameter
+    #[arg(long, value_name = "URL", value_parser = parse_websocket_proxy_url)]
+    pub websocket_proxy: Option<Uri>,
+
+    /// Reach the server, or the websocket proxy, through an HTTP CONNECT or SOCKS5 proxy:
+    /// `http://[user:pass@]host[:port]` or `socks5://[user:pass@]host[:port]`
+    #[arg(long, value_name = "URL")]
+    pub proxy: Option<Proxy>,
+
+    /// Reach the verifier, or the notary, through an HTTP CONNECT or SOCKS5 proxy, given as for
+    /// `--proxy`
+    #[arg(long, value_name = "URL")]
+    pub verifier_proxy: Option<Proxy>,
+
+    /// Notarize with the notary server at this URL instead of proving to the verifier, e.g.
+    /// `https://notary.pse.dev/v0.1.0-alpha.12`. Writes the attestation, the secrets and a
+    /// presentation of what the reveal spec reveals
+    #[arg(long, value_name = "URL", value_parser = parse_notary_url, conflicts_with = "verifier")]
+    pub notary: Option<Uri>,
+
+    /// Where notarization writes the attestation
+    #[arg(long, value_name = "PATH", default_value = "attestation.tlsn")]
+    pub attestation: PathBuf,
+
+    /// Where notarization writes the secrets, from which more presentations can be built. They
+    /// include the whole transcript, so keep them private
+    #[arg(long, value_name = "PATH", default_value = "secrets.tlsn")]
+    pub notarization_secrets: PathBuf,
+
+    /// Where notarization writes the presentation, in tlsn-js's `PresentationJSON` format
+    #[arg(long, value_name = "PATH", default_value = "presentation.json")]
+    pub presentation: PathBuf,
+
+    /// Maximum number of bytes that can be sent from prover to server
+    #[arg(long, default_value_t = 1 << 12)]
+    pub max_sent_data: usize,
+
+    /// Maximum number of bytes that can be received by prover from server
+    #[arg(long, default_value_t = 1 << 14)]
+    pub max_recv_data: usize,
+
+    /// Let the server compress the response. Compressed bodies can only be checked by the
+    /// verifier if fully revealed, so those the reveal spec reveals are revealed in full
+    #[arg(long)]
+    pub compressed: bool,
+
+    /// JSON file listing what to reveal and hide, see `reveal.json`. Defaults to the demo's
+    /// spec, revealing selected fields of the response
+    #[arg(long, value_name = "PATH")]
+    pub reveal: Option<PathBuf>,
+
+    /// Reveal all received data instead of what the reveal spec lists
+    #[arg(long)]
+    pub reveal_all: bool,
+
+    /// Writes the response body to a file, `-` for stdout
+    #[arg(short, long, value_name = "PATH")]
+    pub output: Option<PathBuf>,
+
+    /// Includes the status line and headers of the response in the output
+    #[arg(short, long, requires = "output")]
+    pub include: bool,
+}
+
+impl Args {
+    /// The `-H` headers, or the demo's secret header when fetching the demo URL without any.
+    pub fn headers(&self) -> Vec<(HeaderName, HeaderValue)> {
+        if self.headers.is_empty() && self.url == SERVER_URL {
+            vec![parse_header(SECRET_HEADER).expect("demo header is valid")]
+        } else {
+            self.headers.clone()
+        }
+    }
+
+    /// The secrets from `--secrets-file` and the environment. The demo's secret falls back to a
+    /// built-in value, so the demo runs without setting `DEMO_SECRET`.
+    pub fn secrets(&self) -> Result<Secrets, String> {
+        let secrets = Secrets::load(self.secrets_file.as_deref())?;
+        Ok(if self.url == SERVER_URL {
+            secrets.with_default("DEMO_SECRET", DEMO_SECRET)
+        } else {
+            secrets
+        })
+    }
+
+    /// The request body, read from `--data-file` if given, or `None` if there is none.
+    pub fn body(&self) -> std::io::Result<Option<Bytes>> {
+        match (&self.data, &self.data_file) {
+            (Some(data), _) => Ok(Some(Bytes::from(data.clone()))),
+            (None, Some(path)) => std::fs::read(path).map(|body| Some(Bytes::from(body))),
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// The reveal spec, which hides `--redact` texts and the values of sensitive headers in any
+    /// case.
+    pub fn reveal_spec(&self) -> Result<RevealSpec, String> {
+        let mut spec = match &self.reveal {
+            Some(path) => RevealSpec::from_file(path)?,
+            None => serde_json::from_str(DEMO_REVEAL_SPEC).expect("demo reveal spec is valid"),
+        };
+        if self.reveal_all {
+            spec.received = Rules {
+                reveal: vec![Item::All],
+                hide: Vec::new(),
+            };
+        }
+        spec.sent.hide.extend(
+            self.redact
+                .iter()
+                .map(|text| Item::Regex(regex::escape(text))),
+        );
+
+        let sensitive = |defaults: &[&str], extra: &[String]| {
+            defaults
+                .iter()
+                .map(|name| name.to_string())
+                .chain(extra.iter().cloned())
+                .filter(|name| {
+                    !self
+                        .reveal_header
+                        .iter()
+                        .any(|revealed| revealed.eq_ignore_ascii_case(name))
+                })
+                .map(Item::Header)
+                .collect::<Vec<_>>()
+        };
+        spec.sent
+            .hide
+            .extend(sensitive(SENSITIVE_REQUEST_HEADERS, &self.sensitive_header));
+        spec.received.hide.extend(sensitive(
+            SENSITIVE_RESPONSE_HEADERS,
+            &self.sensitive_header,
+        ));
+        Ok(spec)
+    }
+}
+
+/// A request sent before the main one.
+#[derive(Clone, Debug)]
+pub struct Prelude {
+    pub method: Method,
+    pub path: String,
+    pub body: Option<Bytes>,
+}
+
+fn parse_prelude(prelude: &str) -> Result<Prelude, String> {
+    let parts = prelude.split_whitespace().collect::<Vec<_>>();
+    let (method, path, file) = match parts[..] {
+        [path] => ("GET", path, None),
+        [method, path] => (method, path, None),
+        [method, path, file] => (method, path, Some(file)),
+        _ => return Err(format!("expected `[METHOD] PATH [@FILE]`, got {prelude:?}")),
+    };
+    let method = method
+        .parse::<Method>()
+        .map_err(|_| format!("invalid method {method:?}"))?;
+    if !path.starts_with('/') {
+        return Err(format!("expected a path starting with `/`, got {path:?}"));
+    }
+    let body = file
+        .map(|file| {
+            let path = file
+                .strip_prefix('@')
+                .ok_or_else(|| format!("expected `@FILE`, got {file:?}"))?;
+            std::fs::read(path)
+                .map(Bytes::from)
+                .map_err(|err| format!("failed to read {path}: {err}"))
+        })
+        .transpose()?;
+    Ok(Prelude {
+        method,
+        path: path.to_string(),
+        body,
+    })
+}
+
+fn parse_header(header: &str) -> Result<(HeaderName, HeaderValue), String> {
+    let (name, value) = header
+        .split_once(':')
+        .ok_or_else(|| format!("expected `Name: value`, got {header:?}"))?;
+    let name = HeaderName::from_bytes(name.trim().as_bytes()).map_err(|err| err.to_string())?;
+    let value = HeaderValue::from_str(value.trim()).map_err(|err| err.to_string())?;
+    Ok((name, value))
+}
+
+pub fn parse_server_url(url: &str) -> Result<Uri, String> {
+    let uri = url.parse::<Uri>().map_err(|err| err.to_string())?;
+    if uri.scheme_str() != Some("https") || uri.host().is_none() {
+        return Err("expected an https:// URL".into());
+    }
+    Ok(uri)
+}
+
+fn parse_notary_url(url: &str) -> Result<Uri, String> {
+    let uri = url.parse::<Uri>().map_err(|err| err.to_string())?;
+    if !matches!(uri.scheme_str(), Some("http" | "https")) || uri.host().is_none() {
+        return Err("expected an http:// or https:// URL".into());
+    }
+    Ok(uri)
+}
+
+fn parse_websocket_proxy_url(url: &str) -> Result<Uri, String> {
+    let uri = url.parse::<Uri>().map_err(|err| err.to_string())?;
+    if !matches!(uri.scheme_str(), Some("ws" | "wss")) || uri.host().is_none() {
+        return Err("expected a ws:// or wss:// URL".into());
+    }
+    Ok(uri)
+}
+
+fn parse_verifier_url(url: &str) -> Result<Uri, String> {
+    let uri = url.parse::<Uri>().map_err(|err| err.to_string())?;
+    if !matches!(uri.scheme_str(), Some("http" | "ws")) || uri.host().is_none() {
+        return Err("expected an http:// or ws:// URL".into());
+    }
+    Ok(uri)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_prelude_requests() {
+        let prelude = parse_prelude("/api/me").unwrap();
+        assert_eq!(prelude.method, Method::GET);
+        assert_eq!(prelude.path, "/api/me");
+        assert!(prelude.body.is_none());
+
+        let file = std::env::temp_dir().join(format!("prelude-{}.json", std::process::id()));
+        std::fs::write(&file, r#"{"user": "{{secret:USER}}"}"#).unwrap();
+        let prelude = parse_prelude(&format!("POST /login @{}", file.display())).unwrap();
+        std::fs::remove_file(&file).unwrap();
+        assert_eq!(prelude.method, Method::POST);
+        assert_eq!(prelude.path, "/login");
+        assert_eq!(
+            prelude.body.as_deref(),
+            Some(&br#"{"user": "{{secret:USER}}"}"#[..])
+        );
+
+        assert!(parse_prelude("DELETE /a").is_ok());
+        assert!(parse_prelude("POST login").is_err());
+        assert!(parse_prelude("POST /login body.json").is_err());
+        assert!(parse_prelude("POST /login @missing-prelude-body.json").is_err());
+        assert!(parse_prelude("").is_err());
+    }
+}
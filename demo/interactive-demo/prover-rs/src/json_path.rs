@@ -0,0 +1,173 @@
+//! Exact byte ranges of JSON members found by path.
+//!
+//! Ranges are computed from spansy's parsed spans, so they hold regardless of whitespace,
+//! formatting or escapes. Ranges of strings, keys included, always cover their quotes.
+use spansy::{json::JsonValue, Spanned};
+use std::ops::Range;
+
+use rangeset::RangeSet;
+
+use crate::spec::range;
+
+/// The part of a JSON member to return.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Part {
+    Key,
+    Value,
+    /// The key, the colon and the value, e.g. `"name": "John"`. Array elements have no key,
+    /// so this is just their value.
+    Pair,
+}
+
+/// Finds `part` of the member at a dot-separated `path` in `root`, where numeric segments
+/// index arrays, e.g. `accounts.0.balance`. The spans of `root` must index `src`. Keys are
+/// compared unescaped, so `"na\u006de"` matches `name`.
+pub fn find(src: &[u8], root: &JsonValue, path: &str, part: Part) -> Result<Range<usize>, String> {
+    let mut value = root;
+    // Where the text of `value` starts at the latest, used to locate empty strings, whose spans
+    // are empty.
+    let mut start = range(root.span().indices()).start;
+    let mut key = None;
+    for segment in path.split('.').filter(|segment| !segment.is_empty()) {
+        match value {
+            JsonValue::Object(object) => {
+                let mut next = range(object.span().indices()).start + 1;
+                let mut found = None;
+                for member in &object.elems {
+                    let member_key = string_range(src, member.key.span().indices(), next);
+                    next = value_range(src, &member.value, member_key.end).end;
+                    let matches = serde_json::from_slice::<String>(&src[member_key.clone()])
+                        .is_ok_and(|key| key == segment);
+                    if matches {
+                        found = Some((member_key, &member.value));
+                        break;
+                    }
+                }
+                let (member_key, member_value) = found
+                    .ok_or_else(|| format!("JSON path {path} not found: no key {segment:?}"))?;
+                start = member_key.end;
+                key = Some(member_key);
+                value = member_value;
+            }
+            JsonValue::Array(array) => {
+                let idx = segment
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|&idx| idx < array.elems.len())
+                    .ok_or_else(|| format!("JSON path {path} not found: no element {segment:?}"))?;
+                start = range(array.span().indices()).start + 1;
+                for elem in &array.elems[..idx] {
+                    start = value_range(src, elem, start).end;
+                }
+                key = None;
+                value = &array.elems[idx];
+            }
+            _ => {
+                return Err(format!(
+                    "JSON path {path} not found: {segment:?} is not in an object or array"
+                ))
+            }
+        }
+    }
+
+    let value = value_range(src, value, start);
+    match (part, key) {
+        (Part::Value, _) | (Part::Pair, None) => Ok(value),
+        (Part::Key, Some(key)) => Ok(key),
+        (Part::Pair, Some(key)) => Ok(key.start..value.end),
+        (Part::Key, None) => Err(format!("JSON path {path} has no key")),
+    }
+}
+
+/// The range of a value, starting at `start` or later.
+fn value_range(src: &[u8], value: &JsonValue, start: usize) -> Range<usize> {
+    match value {
+        JsonValue::String(string) => string_range(src, string.span().indices(), start),
+        value => range(value.span().indices()),
+    }
+}
+
+/// The range of a string including its quotes, which its span may or may not cover. String
+/// content never starts with an unescaped quote. An empty string may have an empty span, so it
+/// is the first `""` from `start`.
+fn string_range(src: &[u8], span: &RangeSet<usize>, start: usize) -> Range<usize> {
+    match (span.min(), span.max()) {
+        (Some(min), _) if src[min] == b'"' => range(span),
+        (Some(min), Some(max)) => min - 1..max + 2,
+        _ => {
+            let open = start
+                + src[start..]
+                    .iter()
+                    .position(|&byte| byte == b'"')
+                    .unwrap_or_default();
+            open..open + 2
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spansy::json;
+
+    fn find_str<'a>(src: &'a str, path: &str, part: Part) -> Result<&'a str, String> {
+        let root = json::parse_str(src).unwrap();
+        find(src.as_bytes(), &root, path, part).map(|range| &src[range])
+    }
+
+    #[test]
+    fn finds_members_in_compact_json() {
+        let src =
+            r#"{"information":{"name":"John","address":{"street":"Main St"}},"ids":[7,{"x":""}]}"#;
+
+        assert_eq!(
+            find_str(src, "information.name", Part::Pair),
+            Ok(r#""name":"John""#)
+        );
+        assert_eq!(
+            find_str(src, "information.address.street", Part::Value),
+            Ok(r#""Main St""#)
+        );
+        assert_eq!(find_str(src, "ids.0", Part::Pair), Ok("7"));
+        assert_eq!(find_str(src, "ids.1.x", Part::Pair), Ok(r#""x":"""#));
+        assert_eq!(
+            find_str(src, "ids.1", Part::Key),
+            Err("JSON path ids.1 has no key".into())
+        );
+        assert!(find_str(src, "ids.2", Part::Value).is_err());
+        assert!(find_str(src, "information.age", Part::Value).is_err());
+        assert!(find_str(src, "information.name.first", Part::Value).is_err());
+    }
+
+    #[test]
+    fn finds_members_in_formatted_json() {
+        let src = "{\n  \"name\" :\t\"Jo\\\"hn\" ,\n  \"balance\":  -1.5e3,\n  \"tags\": [ true, null ]\n}";
+
+        assert_eq!(find_str(src, "name", Part::Key), Ok(r#""name""#));
+        assert_eq!(
+            find_str(src, "name", Part::Pair),
+            Ok("\"name\" :\t\"Jo\\\"hn\"")
+        );
+        assert_eq!(find_str(src, "balance", Part::Value), Ok("-1.5e3"));
+        assert_eq!(find_str(src, "tags.1", Part::Value), Ok("null"));
+        assert_eq!(find_str(src, "tags", Part::Value), Ok("[ true, null ]"));
+    }
+
+    #[test]
+    fn handles_escapes_and_empty_strings() {
+        let src = r#"{"": "", "na\u006de": "", "a\"b": ["", "x", ""]}"#;
+
+        assert_eq!(find_str(src, "", Part::Value), Ok(src));
+        assert_eq!(find_str(src, "name", Part::Pair), Ok(r#""na\u006de": """#));
+        assert_eq!(find_str(src, "a\"b", Part::Key), Ok(r#""a\"b""#));
+        assert_eq!(find_str(src, "a\"b.1", Part::Value), Ok(r#""x""#));
+
+        // Empty strings are located by position, not just by content.
+        let root = json::parse_str(src).unwrap();
+        let last = src.rfind(r#""""#).unwrap();
+        assert_eq!(
+            find(src.as_bytes(), &root, "a\"b.2", Part::Value),
+            Ok(last..last + 2)
+        );
+    }
+}
@@ -1,13 +1,13 @@
-use async_tungstenite::{tokio::connect_async_with_config, tungstenite::protocol::WebSocketConfig};
-use http_body_util::Empty;
+use async_tungstenite::{
+    tokio::client_async_with_config,
+    tungstenite::{protocol::WebSocketConfig, Error},
+};
+use clap::Parser;
+use http::header::{ACCEPT_ENCODING, CONNECTION, CONTENT_LENGTH, COOKIE, HOST, SET_COOKIE};
+use http::HeaderValue;
+use http_body_util::{BodyExt, Empty, Full};
 use hyper::{body::Bytes, Request, StatusCode, Uri};
 use hyper_util::rt::TokioIo;
-use rangeset::RangeSet;
-use spansy::{
-    http::parse_response,
-    json::{self},
-    Spanned,
-};
 use tlsn_common::config::ProtocolConfig;
 use tlsn_core::ProveConfig;
 use tlsn_prover::{Prover, ProverConfig};
@@ -17,57 +17,213 @@ use tracing::{debug, info};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
 use ws_stream_tungstenite::WsStream;
 
-const TRACING_FILTER: &str = "INFO";
+mod cli;
+mod json_path;
+mod notary;
+mod proxy;
+mod secrets;
+mod spec;
+mod transport;
 
-const VERIFIER_HOST: &str = "localhost";
-const VERIFIER_PORT: u16 = 9816;
-// Maximum number of bytes that can be sent from prover to server
-const MAX_SENT_DATA: usize = 1 << 12;
-// Maximum number of bytes that can be received by prover from server
-const MAX_RECV_DATA: usize = 1 << 14;
+use cli::Args;
+use notary::NotaryServer;
+use proxy::Proxy;
+use secrets::{Context, Secrets};
+use spec::{Direction, RevealSpec};
 
-const SECRET: &str = "TLSNotary's private key 🤡";
-/// Make sure the following url's domain is the same as SERVER_DOMAIN on the verifier side
-const SERVER_URL: &str = "https://raw.githubusercontent.com/tlsnotary/tlsn/refs/tags/v0.1.0-alpha.12/crates/server-fixture/server/src/data/1kb.json";
+const TRACING_FILTER: &str = "INFO";
 
+/// Protocol offered to the verifier (WebSocket subprotocol or `Upgrade` header), naming the tlsn version this prover is built with
+const TLSN_PROTOCOL: &str = "tlsn/0.1.0-alpha.12";
+/// Header in which the verifier issues a nonce, which is sent back to the server in a header of the same name
+const NONCE_HEADER: &str = "X-TLSN-Nonce";
 #[tokio::main]
 async fn main() {
+    let args = Args::parse();
+
     tracing_subscriber::registry()
         .with(EnvFilter::try_from_default_env().unwrap_or_else(|_| TRACING_FILTER.into()))
-        .with(tracing_subscriber::fmt::layer())
+        // Logs go to stderr, so the response can be written to stdout.
+        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr))
         .init();
 
-    run_prover(VERIFIER_HOST, VERIFIER_PORT, SERVER_URL).await;
+    run_prover(&args).await;
+}
+
+async fn run_prover(args: &Args) {
+    let body = args
+        .body()
+        .unwrap_or_else(|err| panic!("Failed to read the request body: {err}"));
+    let spec = args.reveal_spec().unwrap_or_else(|err| panic!("{err}"));
+    let secrets = args.secrets().unwrap_or_else(|err| panic!("{err}"));
+
+    if let Some(url) = &args.notary {
+        let notary = NotaryServer::new(url.clone());
+        let notary_socket = notary
+            .connect(
+                args.max_sent_data,
+                args.max_recv_data,
+                args.verifier_proxy.as_ref(),
+            )
+            .await;
+        prover(
+            notary_socket,
+            args,
+            &spec,
+            &secrets,
+            body,
+            None,
+            Some(&notary),
+        )
+        .await;
+        return;
+    }
+
+    let verifier_host = args.verifier.host().unwrap();
+    let verifier_port = args.verifier.port_u16().unwrap_or(80);
+    let verifier_path = args.verifier.path();
+    let verifier_proxy = args.verifier_proxy.as_ref();
+    if args.verifier.scheme_str() == Some("ws") {
+        let (verifier_socket, nonce) =
+            connect_verifier_ws(verifier_host, verifier_port, verifier_path, verifier_proxy).await;
+        prover(
+            verifier_socket,
+            args,
+            &spec,
+            &secrets,
+            body,
+            nonce.as_deref(),
+            None,
+        )
+        .await;
+    } else {
+        let (verifier_socket, nonce) =
+            connect_verifier_tcp(verifier_host, verifier_port, verifier_path, verifier_proxy).await;
+        prover(
+            verifier_socket,
+            args,
+            &spec,
+            &secrets,
+            body,
+            nonce.as_deref(),
+            None,
+        )
+        .await;
+    }
+    info!("Proving is successful!");
 }
 
-async fn run_prover(verifier_host: &str, verifier_port: u16, server_uri: &str) {
+/// Connects to the verifier over WebSocket, returning the nonce it issued, if any.
+async fn connect_verifier_ws(
+    verifier_host: &str,
+    verifier_port: u16,
+    verifier_path: &str,
+    verifier_proxy: Option<&Proxy>,
+) -> (
+    impl AsyncWrite + AsyncRead + Send + Unpin + 'static,
+    Option<String>,
+) {
     info!("Sending websocket request...");
     let request = http::Request::builder()
-        .uri(format!("ws://{verifier_host}:{verifier_port}/verify",))
+        .uri(format!(
+            "ws://{verifier_host}:{verifier_port}{verifier_path}"
+        ))
         .header("Host", verifier_host)
         .header("Sec-WebSocket-Key", uuid::Uuid::new_v4().to_string())
         .header("Sec-WebSocket-Version", "13")
         .header("Connection", "Upgrade")
         .header("Upgrade", "Websocket")
+        .header("Sec-WebSocket-Protocol", TLSN_PROTOCOL)
         .body(())
         .unwrap();
 
-    let (verifier_ws_stream, _) =
-        connect_async_with_config(request, Some(WebSocketConfig::default()))
+    let verifier_socket =
+        transport::connect_tcp(verifier_host, verifier_port, verifier_proxy).await;
+    let (verifier_ws_stream, response) =
+        match client_async_with_config(request, verifier_socket, Some(WebSocketConfig::default()))
             .await
-            .unwrap();
+        {
+            Ok(connection) => connection,
+            // Surface the verifier's reason, e.g. a list of supported protocol versions.
+            Err(Error::Http(response)) => panic!(
+                "Verifier refused the websocket upgrade ({}): {}",
+                response.status(),
+                String::from_utf8_lossy(response.body().as_deref().unwrap_or_default())
+            ),
+            Err(err) => panic!("Failed to connect to verifier: {err}"),
+        };
 
     info!("Websocket connection established!");
-    let verifier_ws_socket = WsStream::new(verifier_ws_stream);
-    prover(verifier_ws_socket, server_uri).await;
-    info!("Proving is successful!");
+    (WsStream::new(verifier_ws_stream), nonce(response.headers()))
+}
+
+/// Connects to the verifier over raw TCP, using an HTTP `Upgrade: tlsn` request, returning the
+/// nonce it issued, if any.
+async fn connect_verifier_tcp(
+    verifier_host: &str,
+    verifier_port: u16,
+    verifier_path: &str,
+    verifier_proxy: Option<&Proxy>,
+) -> (
+    impl AsyncWrite + AsyncRead + Send + Unpin + 'static,
+    Option<String>,
+) {
+    info!("Sending tcp upgrade request...");
+    let verifier_socket =
+        transport::connect_tcp(verifier_host, verifier_port, verifier_proxy).await;
+    let (mut request_sender, connection) =
+        hyper::client::conn::http1::handshake(TokioIo::new(verifier_socket))
+            .await
+            .unwrap();
+    tokio::spawn(connection.with_upgrades());
+
+    let request = Request::builder()
+        .uri(verifier_path)
+        .header("Host", verifier_host)
+        .header("Connection", "Upgrade")
+        .header("Upgrade", TLSN_PROTOCOL)
+        .body(Empty::<Bytes>::new())
+        .unwrap();
+    let response = request_sender.send_request(request).await.unwrap();
+
+    if response.status() != StatusCode::SWITCHING_PROTOCOLS {
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        // Surface the verifier's reason, e.g. a list of supported protocol versions.
+        panic!(
+            "Verifier refused the tcp upgrade ({status}): {}",
+            String::from_utf8_lossy(&body)
+        );
+    }
+
+    let nonce = nonce(response.headers());
+    let upgraded = hyper::upgrade::on(response).await.unwrap();
+    info!("TCP connection established!");
+    (TokioIo::new(upgraded), nonce)
 }
 
-async fn prover<T: AsyncWrite + AsyncRead + Send + Unpin + 'static>(verifier_socket: T, uri: &str) {
+/// Reads the nonce issued by the verifier from the upgrade response.
+fn nonce(headers: &http::HeaderMap) -> Option<String> {
+    let nonce = headers.get(NONCE_HEADER)?.to_str().ok()?.to_string();
+    debug!("Verifier issued nonce {nonce}");
+    Some(nonce)
+}
+
+async fn prover<T: AsyncWrite + AsyncRead + Send + Unpin + 'static>(
+    verifier_socket: T,
+    args: &Args,
+    spec: &RevealSpec,
+    secrets: &Secrets,
+    body: Option<Bytes>,
+    nonce: Option<&str>,
+    notary: Option<&NotaryServer>,
+) {
     debug!("Starting proving...");
 
-    let uri = uri.parse::<Uri>().unwrap();
-    assert_eq!(uri.scheme().unwrap().as_str(), "https");
+    let uri = &secrets
+        .resolve(&args.url, Context::Url)
+        .and_then(|url| cli::parse_server_url(&url))
+        .unwrap_or_else(|err| panic!("Invalid server URL: {err}"));
     let server_domain = uri.authority().unwrap().host();
     let server_port = uri.port_u16().unwrap_or(443);
 
@@ -79,8 +235,8 @@ async fn prover<T: AsyncWrite + AsyncRead + Send + Unpin + 'static>(verifier_soc
             .server_name(server_domain)
             .protocol_config(
                 ProtocolConfig::builder()
-                    .max_sent_data(MAX_SENT_DATA)
-                    .max_recv_data(MAX_RECV_DATA)
+                    .max_sent_data(args.max_sent_data)
+                    .max_recv_data(args.max_recv_data)
                     .build()
                     .unwrap(),
             )
@@ -92,9 +248,13 @@ async fn prover<T: AsyncWrite + AsyncRead + Send + Unpin + 'static>(verifier_soc
     .unwrap();
 
     // Connect to TLS Server.
-    let tls_client_socket = tokio::net::TcpStream::connect((server_domain, server_port))
-        .await
-        .unwrap();
+    let tls_client_socket = transport::connect_server(
+        server_domain,
+        server_port,
+        args.websocket_proxy.as_ref(),
+        args.proxy.as_ref(),
+    )
+    .await;
 
     // Pass server connection into the prover.
     let (mpc_tls_connection, prover_fut) =
@@ -114,78 +274,248 @@ async fn prover<T: AsyncWrite + AsyncRead + Send + Unpin + 'static>(verifier_soc
 
     tokio::spawn(connection);
 
+    // MPC-TLS: Send the prelude requests, keeping the connection alive. Cookies they set are
+    // sent with the requests after them.
+    let mut cookies = Vec::new();
+    for prelude in &args.prelude {
+        let prelude_uri = Uri::builder()
+            .scheme("https")
+            .authority(uri.authority().unwrap().clone())
+            .path_and_query(
+                secrets
+                    .resolve(&prelude.path, Context::Url)
+                    .unwrap_or_else(|err| panic!("{err}")),
+            )
+            .build()
+            .unwrap();
+        info!(
+            "Send {} Request to {} and wait for Response",
+            prelude.method, prelude.path
+        );
+        let mut request = server_request(
+            args,
+            secrets,
+            prelude.method.clone(),
+            prelude_uri,
+            prelude.body.clone(),
+            nonce,
+            false,
+        );
+        add_cookies(&mut request, &cookies);
+        let response = request_sender.send_request(request).await.unwrap();
+        debug!("TLS response: {:?}", response);
+        // Logins commonly answer with a redirect, which is not followed.
+        assert!(
+            response.status().is_success() || response.status().is_redirection(),
+            "Server responded to {} {} with {}",
+            prelude.method,
+            prelude.path,
+            response.status()
+        );
+        store_cookies(&mut cookies, response.headers());
+        // The whole body must be read before the connection can be reused.
+        response.into_body().collect().await.unwrap();
+    }
+
     // MPC-TLS: Send Request and wait for Response.
     info!("Send Request and wait for Response");
-    let request = Request::builder()
-        .uri(uri.clone())
-        .header("Host", server_domain)
-        .header("Connection", "close")
-        .header("Secret", SECRET)
-        .method("GET")
-        .body(Empty::<Bytes>::new())
-        .unwrap();
+    let mut request = server_request(
+        args,
+        secrets,
+        args.method.clone(),
+        uri.clone(),
+        body,
+        nonce,
+        true,
+    );
+    add_cookies(&mut request, &cookies);
     let response = request_sender.send_request(request).await.unwrap();
 
     debug!("TLS response: {:?}", response);
-    assert!(response.status() == StatusCode::OK);
+    assert!(
+        response.status().is_success(),
+        "Server responded with {}",
+        response.status()
+    );
+    let head = args.include.then(|| response_head_text(&response));
+    let response_body = response.into_body().collect().await.unwrap().to_bytes();
 
     // Create proof for the Verifier.
     let mut prover = prover_task.await.unwrap().unwrap();
 
-    let mut builder: tlsn_core::ProveConfigBuilder<'_> = ProveConfig::builder(prover.transcript());
-
-    // Reveal the DNS name.
-    builder.server_identity();
-
-    let sent_rangeset = redact_and_reveal_sent_data(prover.transcript().sent());
-    let _ = builder.reveal_sent(&sent_rangeset);
-
-    let recv_rangeset = redact_and_reveal_received_data(prover.transcript().received());
-    let _ = builder.reveal_recv(&recv_rangeset);
-
-    let config = builder.build().unwrap();
-
-    prover.prove(&config).await.unwrap();
-    prover.close().await.unwrap();
+    debug!(
+        "Sent data: {}",
+        String::from_utf8_lossy(prover.transcript().sent())
+    );
+    debug!(
+        "Received data: {}",
+        String::from_utf8_lossy(prover.transcript().received())
+    );
+
+    // Substituted secrets are never revealed, wherever they occur.
+    let mut spec = spec.clone();
+    for secret in secrets.resolved() {
+        spec.hide_everywhere(&secret);
+    }
+
+    let sent_rangeset = spec
+        .sent
+        .compile(prover.transcript().sent(), Direction::Sent)
+        .unwrap_or_else(|err| panic!("{err}"));
+
+    let recv_rangeset = spec
+        .received
+        .compile(prover.transcript().received(), Direction::Received)
+        .unwrap_or_else(|err| panic!("{err}"));
+
+    if let Some(notary) = notary {
+        notary::notarize(
+            prover,
+            &sent_rangeset,
+            &recv_rangeset,
+            notary,
+            args.websocket_proxy.as_ref(),
+            notary::Outputs {
+                attestation: &args.attestation,
+                secrets: &args.notarization_secrets,
+                presentation: &args.presentation,
+            },
+        )
+        .await;
+    } else {
+        let mut builder: tlsn_core::ProveConfigBuilder<'_> =
+            ProveConfig::builder(prover.transcript());
+
+        // Reveal the DNS name.
+        builder.server_identity();
+        let _ = builder.reveal_sent(&sent_rangeset);
+        let _ = builder.reveal_recv(&recv_rangeset);
+
+        let config = builder.build().unwrap();
+
+        prover.prove(&config).await.unwrap();
+        prover.close().await.unwrap();
+    }
+
+    if let Some(path) = &args.output {
+        let mut output = head.unwrap_or_default().into_bytes();
+        output.extend_from_slice(&response_body);
+        if path.as_os_str() == "-" {
+            std::io::Write::write_all(&mut std::io::stdout(), &output).unwrap();
+        } else {
+            std::fs::write(path, output)
+                .unwrap_or_else(|err| panic!("Failed to write {}: {err}", path.display()));
+        }
+    }
 }
 
-/// Redacts and reveals received data to the verifier.
-fn redact_and_reveal_received_data(recv_transcript: &[u8]) -> RangeSet<usize> {
-    // Get the some information from the received data.
-    let received_string = String::from_utf8(recv_transcript.to_vec()).unwrap();
-    debug!("Received data: {}", received_string);
-    let resp = parse_response(recv_transcript).unwrap();
-    let body = resp.body.unwrap();
-    let mut json = json::parse_slice(body.as_bytes()).unwrap();
-    json.offset(body.content.span().indices().min().unwrap());
-
-    let name = json.get("information.name").expect("name field not found");
-
-    let street = json
-        .get("information.address.street")
-        .expect("street field not found");
-
-    let name_start = name.span().indices().min().unwrap() - 9; // 9 is the length of "name: "
-    let name_end = name.span().indices().max().unwrap() + 1; // include `"`
-    let street_start = street.span().indices().min().unwrap() - 11; // 11 is the length of "street: "
-    let street_end = street.span().indices().max().unwrap() + 1; // include `"`
-
-    [name_start..name_end + 1, street_start..street_end + 1].into()
+/// Keeps the `name=value` pairs of the `Set-Cookie` headers in `headers`, replacing earlier
+/// cookies of the same name. Cookie attributes are ignored.
+fn store_cookies(cookies: &mut Vec<String>, headers: &http::HeaderMap) {
+    for value in headers.get_all(SET_COOKIE) {
+        let Ok(value) = value.to_str() else {
+            continue;
+        };
+        let cookie = value.split(';').next().unwrap_or_default().trim();
+        let Some((name, _)) = cookie.split_once('=') else {
+            continue;
+        };
+        cookies.retain(|kept: &String| kept.split_once('=').map(|(kept, _)| kept) != Some(name));
+        cookies.push(cookie.to_string());
+    }
 }
 
-/// Redacts and reveals sent data to the verifier.
-fn redact_and_reveal_sent_data(sent_transcript: &[u8]) -> RangeSet<usize> {
-    let sent_transcript_len = sent_transcript.len();
+/// Builds a request to the server, closing the connection after the last one. The verifier's
+/// nonce, if any, is embedded in a header so it is bound into the transcript. Secret
+/// placeholders in the headers and body are resolved here, just before sending.
+fn server_request(
+    args: &Args,
+    secrets: &Secrets,
+    method: http::Method,
+    uri: Uri,
+    body: Option<Bytes>,
+    nonce: Option<&str>,
+    last: bool,
+) -> Request<Full<Bytes>> {
+    let host = uri.authority().unwrap().host().to_string();
+    let body = body.map(|body| {
+        Bytes::from(
+            secrets
+                .resolve_body(&body)
+                .unwrap_or_else(|err| panic!("{err}")),
+        )
+    });
+    let content_length = body.as_ref().map(Bytes::len);
+    let mut request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .body(Full::new(body.unwrap_or_default()))
+        .unwrap();
 
-    let sent_string: String = String::from_utf8(sent_transcript.to_vec()).unwrap();
-    let secret_start = sent_string.find(SECRET).unwrap();
+    let headers = request.headers_mut();
+    headers.insert(HOST, HeaderValue::from_str(&host).unwrap());
+    // As in tlsn-js, any given body, even an empty one, is sent with its length, here in bytes
+    // of the resolved body.
+    if let Some(length) = content_length {
+        headers.insert(CONTENT_LENGTH, HeaderValue::from(length));
+    }
+    headers.insert(
+        CONNECTION,
+        HeaderValue::from_static(if last { "close" } else { "keep-alive" }),
+    );
+    headers.insert(
+        ACCEPT_ENCODING,
+        HeaderValue::from_static(if args.compressed {
+            "gzip, deflate, br"
+        } else {
+            "identity"
+        }),
+    );
+    if let Some(nonce) = nonce {
+        headers.insert(NONCE_HEADER, HeaderValue::from_str(nonce).unwrap());
+    }
+    // Headers from the command line replace the defaults above.
+    let extra_headers = args.headers();
+    for (name, _) in &extra_headers {
+        headers.remove(name);
+    }
+    for (name, value) in &extra_headers {
+        let value = value
+            .to_str()
+            .map_err(|err| err.to_string())
+            .and_then(|value| secrets.resolve(value, Context::Raw))
+            .and_then(|value| HeaderValue::from_str(&value).map_err(|err| err.to_string()))
+            .unwrap_or_else(|err| panic!("Invalid value of header {name}: {err}"));
+        headers.append(name, value);
+    }
+    request
+}
 
-    debug!("Send data: {}", sent_string);
+/// Adds `cookies` to the `Cookie` header of `request`, after any given on the command line.
+fn add_cookies(request: &mut Request<Full<Bytes>>, cookies: &[String]) {
+    if cookies.is_empty() {
+        return;
+    }
+    let headers = request.headers_mut();
+    let mut cookie = headers
+        .remove(COOKIE)
+        .map(|value| format!("{}; ", String::from_utf8_lossy(value.as_bytes())))
+        .unwrap_or_default();
+    cookie.push_str(&cookies.join("; "));
+    let value = HeaderValue::from_str(&cookie)
+        .unwrap_or_else(|err| panic!("Invalid cookie set by the server: {err}"));
+    headers.insert(COOKIE, value);
+}
 
-    // Reveal everything except for the SECRET.
-    [
-        0..secret_start,
-        secret_start + SECRET.len()..sent_transcript_len,
-    ]
-    .into()
+/// The status line and headers of a response, as written by `--include`.
+fn response_head_text<B>(response: &hyper::Response<B>) -> String {
+    let mut head = format!("{:?} {}\r\n", response.version(), response.status());
+    for (name, value) in response.headers() {
+        head.push_str(&format!(
+            "{name}: {}\r\n",
+            String::from_utf8_lossy(value.as_bytes())
+        ));
+    }
+    head.push_str("\r\n");
+    head
 }
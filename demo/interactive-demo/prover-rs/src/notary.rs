@@ -0,0 +1,285 @@
+//! Notarization with a notary server, as tlsn-js does it.
+//!
+//! A session is requested with `POST /session`, then the prover connects to
+//! `/notarize?sessionId=` over WebSocket. The attestation and secrets the notarization yields are
+//! saved, and a presentation built from them is written in tlsn-js's `PresentationJSON` format.
+use async_tungstenite::tokio::client_async_tls;
+use http::{header, Request, Uri};
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use hyper_util::rt::TokioIo;
+use rangeset::RangeSet;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tlsn_core::{
+    presentation::Presentation, request::RequestConfig, transcript::TranscriptCommitConfig,
+    CryptoProvider,
+};
+use tlsn_prover::{state::Committed, Prover};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_native_tls::{native_tls, TlsConnector};
+use tracing::info;
+use ws_stream_tungstenite::WsStream;
+
+use crate::{proxy::Proxy, transport};
+
+/// Version of the presentations written, that of the tlsn this prover is built with.
+pub const PRESENTATION_VERSION: &str = "0.1.0-alpha.12";
+
+/// A presentation in tlsn-js's `PresentationJSON` format.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PresentationJson {
+    pub version: String,
+    /// The bincode-serialized presentation, hex encoded.
+    pub data: String,
+    pub meta: PresentationMeta,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PresentationMeta {
+    pub notary_url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub websocket_proxy_url: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SessionRequest {
+    client_type: &'static str,
+    max_sent_data: usize,
+    max_recv_data: usize,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SessionResponse {
+    session_id: String,
+}
+
+/// A notary server, given by its `http://` or `https://` URL, possibly with a path prefix, e.g.
+/// `https://notary.pse.dev/v0.1.0-alpha.12`.
+#[derive(Clone, Debug)]
+pub struct NotaryServer {
+    url: Uri,
+}
+
+impl NotaryServer {
+    pub fn new(url: Uri) -> Self {
+        Self { url }
+    }
+
+    fn tls(&self) -> bool {
+        self.url.scheme_str() == Some("https")
+    }
+
+    fn host(&self) -> &str {
+        self.url.host().unwrap()
+    }
+
+    fn port(&self) -> u16 {
+        self.url
+            .port_u16()
+            .unwrap_or(if self.tls() { 443 } else { 80 })
+    }
+
+    /// The URL without the trailing slash, to which endpoint paths are appended.
+    fn base(&self) -> String {
+        let authority = self.url.authority().unwrap();
+        format!("{}{}", authority, self.url.path().trim_end_matches('/'))
+    }
+
+    /// The scheme and host of the notary, as recorded in presentations by tlsn-js.
+    pub fn normalized_url(&self) -> String {
+        let scheme = if self.tls() { "https" } else { "http" };
+        format!("{scheme}://{}", self.url.authority().unwrap())
+    }
+
+    /// Requests a notarization session and connects to it, through `proxy` if given.
+    pub async fn connect(
+        &self,
+        max_sent_data: usize,
+        max_recv_data: usize,
+        proxy: Option<&Proxy>,
+    ) -> impl AsyncRead + AsyncWrite + Send + Unpin + 'static {
+        let session = SessionRequest {
+            client_type: "Websocket",
+            max_sent_data,
+            max_recv_data,
+        };
+        let socket = transport::connect_tcp(self.host(), self.port(), proxy).await;
+        let session_id = if self.tls() {
+            let connector = TlsConnector::from(native_tls::TlsConnector::new().unwrap());
+            let socket = connector
+                .connect(self.host(), socket)
+                .await
+                .unwrap_or_else(|err| panic!("Failed to connect to notary {}: {err}", self.url));
+            self.request_session(socket, &session).await
+        } else {
+            self.request_session(socket, &session).await
+        };
+        info!("Notary issued session {session_id}");
+
+        let scheme = if self.tls() { "wss" } else { "ws" };
+        let url = format!("{scheme}://{}/notarize?sessionId={session_id}", self.base());
+        let socket = transport::connect_tcp(self.host(), self.port(), proxy).await;
+        let (stream, _) = client_async_tls(url, socket)
+            .await
+            .unwrap_or_else(|err| panic!("Failed to connect to notary {}: {err}", self.url));
+        WsStream::new(stream)
+    }
+
+    async fn request_session<S>(&self, socket: S, session: &SessionRequest) -> String
+    where
+        S: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    {
+        let (mut request_sender, connection) =
+            hyper::client::conn::http1::handshake(TokioIo::new(socket))
+                .await
+                .unwrap();
+        tokio::spawn(connection);
+
+        let request = Request::post(format!("{}/session", self.url.path().trim_end_matches('/')))
+            .header(header::HOST, self.url.authority().unwrap().as_str())
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Full::new(Bytes::from(serde_json::to_vec(session).unwrap())))
+            .unwrap();
+        let response = request_sender
+            .send_request(request)
+            .await
+            .unwrap_or_else(|err| panic!("Failed to request a notary session: {err}"));
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        if !status.is_success() {
+            panic!(
+                "Notary refused the session ({status}): {}",
+                String::from_utf8_lossy(&body)
+            );
+        }
+        serde_json::from_slice::<SessionResponse>(&body)
+            .ok()
+            .filter(|session| !session.session_id.is_empty())
+            .unwrap_or_else(|| panic!("Notary sent an invalid session id"))
+            .session_id
+    }
+}
+
+/// Where notarization writes its results.
+pub struct Outputs<'a> {
+    pub attestation: &'a Path,
+    pub secrets: &'a Path,
+    pub presentation: &'a Path,
+}
+
+/// Notarizes the session and writes the attestation, the secrets and a presentation revealing
+/// `sent` and `recv`, along with the server's identity.
+pub async fn notarize(
+    mut prover: Prover<Committed>,
+    sent: &RangeSet<usize>,
+    recv: &RangeSet<usize>,
+    notary: &NotaryServer,
+    websocket_proxy: Option<&Uri>,
+    outputs: Outputs<'_>,
+) {
+    // Commit to the whole transcript, as tlsn-js does by default, so that any part of it can
+    // be presented later from the saved secrets.
+    let mut builder = TranscriptCommitConfig::builder(prover.transcript());
+    builder
+        .commit_sent(&(0..prover.transcript().sent().len()))
+        .unwrap();
+    builder
+        .commit_recv(&(0..prover.transcript().received().len()))
+        .unwrap();
+    let transcript_commit = builder.build().unwrap();
+
+    let mut builder = RequestConfig::builder();
+    builder.transcript_commit(transcript_commit);
+    let request_config = builder.build().unwrap();
+
+    info!("Notarizing...");
+    let (attestation, secrets) = prover.notarize(&request_config).await.unwrap();
+    prover.close().await.unwrap();
+    info!("Notarization is successful!");
+
+    write(
+        outputs.attestation,
+        &bincode::serialize(&attestation).unwrap(),
+    );
+    write(outputs.secrets, &bincode::serialize(&secrets).unwrap());
+
+    let mut builder = secrets.transcript_proof_builder();
+    builder
+        .reveal_sent(sent)
+        .unwrap_or_else(|err| panic!("Failed to reveal sent data: {err}"));
+    builder
+        .reveal_recv(recv)
+        .unwrap_or_else(|err| panic!("Failed to reveal received data: {err}"));
+    let transcript_proof = builder.build().unwrap();
+
+    let provider = CryptoProvider::default();
+    let mut builder = attestation.presentation_builder(&provider);
+    builder
+        .identity_proof(secrets.identity_proof())
+        .transcript_proof(transcript_proof);
+    let presentation: Presentation = builder.build().unwrap();
+
+    let presentation = PresentationJson {
+        version: PRESENTATION_VERSION.into(),
+        data: bincode::serialize(&presentation)
+            .unwrap()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect(),
+        meta: PresentationMeta {
+            notary_url: notary.normalized_url(),
+            websocket_proxy_url: websocket_proxy.map(Uri::to_string),
+        },
+    };
+    write(
+        outputs.presentation,
+        &serde_json::to_vec_pretty(&presentation).unwrap(),
+    );
+    info!(
+        "Wrote the attestation to {}, the secrets to {} and the presentation to {}",
+        outputs.attestation.display(),
+        outputs.secrets.display(),
+        outputs.presentation.display()
+    );
+}
+
+fn write(path: &Path, data: &[u8]) {
+    std::fs::write(path, data)
+        .unwrap_or_else(|err| panic!("Failed to write {}: {err}", path.display()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_presentations_as_tlsn_js() {
+        let notary = NotaryServer::new("https://notary.pse.dev/v0.1.0-alpha.12/".parse().unwrap());
+        assert_eq!(notary.normalized_url(), "https://notary.pse.dev");
+        assert_eq!(notary.base(), "notary.pse.dev/v0.1.0-alpha.12");
+
+        let presentation = PresentationJson {
+            version: PRESENTATION_VERSION.into(),
+            data: "0a1b".into(),
+            meta: PresentationMeta {
+                notary_url: notary.normalized_url(),
+                websocket_proxy_url: Some("wss://notary.pse.dev/proxy".into()),
+            },
+        };
+        assert_eq!(
+            serde_json::to_value(&presentation).unwrap(),
+            serde_json::json!({
+                "version": "0.1.0-alpha.12",
+                "data": "0a1b",
+                "meta": {
+                    "notaryUrl": "https://notary.pse.dev",
+                    "websocketProxyUrl": "wss://notary.pse.dev/proxy"
+                }
+            })
+        );
+    }
+}
@@ -0,0 +1,220 @@
+//! Secret placeholders in request templates.
+//!
+//! The URL, headers, bodies and prelude paths may contain placeholders such as
+//! `{{secret:GITHUB_TOKEN}}`, resolved from a secrets file or the environment just before the
+//! request is sent. Values are escaped for where they are substituted: percent-encoded in URLs
+//! and escaped as in a JSON string in JSON bodies. Every resolved value is recorded, both as
+//! given and as sent, so it can be hidden from the verifier wherever it occurs in the transcript.
+use std::{cell::RefCell, collections::HashMap, path::Path};
+
+const PREFIX: &str = "{{secret:";
+const SUFFIX: &str = "}}";
+
+/// Where a template is substituted, deciding how secrets are escaped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Context {
+    /// As is, e.g. in header values.
+    Raw,
+    /// Percent-encoded, except for unreserved characters.
+    Url,
+    /// Escaped as in a JSON string, for placeholders inside JSON strings.
+    Json,
+}
+
+impl Context {
+    fn escape(self, value: &str) -> String {
+        match self {
+            Context::Raw => value.to_string(),
+            Context::Url => value
+                .bytes()
+                .map(|byte| match byte {
+                    b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                        (byte as char).to_string()
+                    }
+                    _ => format!("%{byte:02X}"),
+                })
+                .collect(),
+            Context::Json => {
+                let quoted = serde_json::to_string(value).expect("strings serialize");
+                quoted[1..quoted.len() - 1].to_string()
+            }
+        }
+    }
+}
+
+/// Resolves secret placeholders, remembering the values it substituted.
+#[derive(Debug, Default)]
+pub struct Secrets {
+    file: HashMap<String, String>,
+    defaults: HashMap<String, String>,
+    resolved: RefCell<Vec<String>>,
+}
+
+impl Secrets {
+    /// Looks up secrets in `file`, if given, then in environment variables. The file holds
+    /// `NAME=value` lines; blank lines and lines starting with `#` are ignored.
+    pub fn load(file: Option<&Path>) -> Result<Self, String> {
+        let Some(path) = file else {
+            return Ok(Self::default());
+        };
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| format!("Failed to read secrets file {}: {err}", path.display()))?;
+        let mut secrets = HashMap::new();
+        for (idx, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, value) = line.split_once('=').ok_or_else(|| {
+                format!(
+                    "Invalid secrets file {} at line {}, expected NAME=value",
+                    path.display(),
+                    idx + 1
+                )
+            })?;
+            secrets.insert(name.trim().to_string(), value.trim().to_string());
+        }
+        Ok(Self {
+            file: secrets,
+            ..Default::default()
+        })
+    }
+
+    /// Falls back to `value` for the secret `name` if neither the file nor the environment
+    /// sets it.
+    pub fn with_default(mut self, name: &str, value: &str) -> Self {
+        self.defaults.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// Replaces the placeholders in `template` with secrets escaped for `context`.
+    pub fn resolve(&self, template: &str, context: Context) -> Result<String, String> {
+        let mut output = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(start) = rest.find(PREFIX) {
+            let name_start = start + PREFIX.len();
+            let name_len = rest[name_start..]
+                .find(SUFFIX)
+                .ok_or_else(|| format!("Unterminated secret placeholder in {template:?}"))?;
+            let name = &rest[name_start..name_start + name_len];
+            let value = self.value(name)?;
+
+            let escaped = context.escape(&value);
+            output.push_str(&rest[..start]);
+            output.push_str(&escaped);
+            if !value.is_empty() {
+                let mut resolved = self.resolved.borrow_mut();
+                if escaped != value {
+                    resolved.push(escaped);
+                }
+                resolved.push(value);
+            }
+            rest = &rest[name_start + name_len + SUFFIX.len()..];
+        }
+        output.push_str(rest);
+        Ok(output)
+    }
+
+    /// Replaces the placeholders in the body `template` if it is text, leaving other data as
+    /// is. Secrets are JSON-escaped if the template is JSON, with its placeholders in strings.
+    pub fn resolve_body(&self, template: &[u8]) -> Result<Vec<u8>, String> {
+        let Ok(text) = std::str::from_utf8(template) else {
+            return Ok(template.to_vec());
+        };
+        let context = if serde_json::from_slice::<serde_json::Value>(template).is_ok() {
+            Context::Json
+        } else {
+            Context::Raw
+        };
+        self.resolve(text, context).map(String::into_bytes)
+    }
+
+    /// The values substituted so far, as given and as escaped where they differ.
+    pub fn resolved(&self) -> Vec<String> {
+        self.resolved.borrow().clone()
+    }
+
+    fn value(&self, name: &str) -> Result<String, String> {
+        if let Some(value) = self.file.get(name) {
+            return Ok(value.clone());
+        }
+        std::env::var(name)
+            .ok()
+            .or_else(|| self.defaults.get(name).cloned())
+            .ok_or_else(|| {
+                format!("Secret {name} is not set in the secrets file or the environment")
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_placeholders() {
+        let secrets = Secrets {
+            file: HashMap::from([
+                ("TOKEN".into(), "s3cr3t".into()),
+                ("EMPTY".into(), "".into()),
+            ]),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            secrets.resolve(
+                "Bearer {{secret:TOKEN}}{{secret:EMPTY}}, {{secret:TOKEN}}",
+                Context::Raw
+            ),
+            Ok("Bearer s3cr3t, s3cr3t".into())
+        );
+        assert_eq!(secrets.resolved(), ["s3cr3t", "s3cr3t"]);
+        assert_eq!(
+            secrets.resolve("{{ plain }}", Context::Raw),
+            Ok("{{ plain }}".into())
+        );
+        assert!(secrets.resolve("{{secret:TOKEN", Context::Raw).is_err());
+        assert!(secrets
+            .resolve("{{secret:TLSN_PROVER_UNSET_SECRET}}", Context::Raw)
+            .unwrap_err()
+            .contains("TLSN_PROVER_UNSET_SECRET is not set"));
+
+        let secrets = secrets.with_default("TLSN_PROVER_UNSET_SECRET", "demo");
+        assert_eq!(
+            secrets.resolve("{{secret:TLSN_PROVER_UNSET_SECRET}}", Context::Raw),
+            Ok("demo".into())
+        );
+    }
+
+    #[test]
+    fn escapes_secrets_where_substituted() {
+        let secrets = Secrets {
+            file: HashMap::from([("KEY".into(), "a b&\"c\\🤡".into())]),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            secrets.resolve("/api?key={{secret:KEY}}", Context::Url),
+            Ok("/api?key=a%20b%26%22c%5C%F0%9F%A4%A1".into())
+        );
+        assert_eq!(
+            secrets.resolve_body(br#"{"key": "{{secret:KEY}}"}"#),
+            Ok(r#"{"key": "a b&\"c\\🤡"}"#.as_bytes().to_vec())
+        );
+        assert_eq!(
+            secrets.resolve_body(b"key={{secret:KEY}}"),
+            Ok("key=a b&\"c\\🤡".as_bytes().to_vec())
+        );
+        // What was sent is hidden, and so is the secret itself wherever the server echoes it.
+        assert_eq!(
+            secrets.resolved(),
+            [
+                "a%20b%26%22c%5C%F0%9F%A4%A1",
+                "a b&\"c\\🤡",
+                "a b&\\\"c\\\\🤡",
+                "a b&\"c\\🤡",
+                "a b&\"c\\🤡",
+            ]
+        );
+    }
+}
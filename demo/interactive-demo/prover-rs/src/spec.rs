@@ -0,0 +1,367 @@
+//! Declarative description of which parts of the transcript are revealed to the verifier.
+//!
+//! A [`RevealSpec`] lists, for the sent and the received data, what to reveal and then what to
+//! hide again, e.g.
+//!
+//! ```json
+//! {
+//!   "sent": { "reveal": ["all"], "hide": [{ "header": "Authorization" }] },
+//!   "received": { "reveal": ["head", { "json": "information.name" }] }
+//! }
+//! ```
+//!
+//! Items apply to every request or response of the session. An item to reveal that matches
+//! nothing is an error, so a renamed field never silently goes unproven, and a JSON path to
+//! reveal must be found in every JSON body it applies to.
+//!
+//! A compressed body can only be checked by the verifier in full, so one that is revealed at
+//! all, or targeted by a JSON path, is revealed whole. Hidden items are still hidden within it.
+use regex::bytes::Regex;
+use serde::Deserialize;
+use spansy::{
+    http::{Body, Header, Requests, Responses},
+    json, Spanned,
+};
+use std::{fmt, ops::Range};
+
+use rangeset::RangeSet;
+
+use crate::json_path::{self, Part};
+
+/// Request headers carrying credentials, whose values are hidden unless explicitly revealed.
+pub const SENSITIVE_REQUEST_HEADERS: &[&str] = &[
+    "Authorization",
+    "Cookie",
+    "X-API-Key",
+    "Proxy-Authorization",
+];
+/// Response headers carrying credentials, whose values are hidden unless explicitly revealed.
+pub const SENSITIVE_RESPONSE_HEADERS: &[&str] = &["Set-Cookie"];
+
+/// Which parts of the sent and received data to reveal.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RevealSpec {
+    #[serde(default)]
+    pub sent: Rules,
+    #[serde(default)]
+    pub received: Rules,
+}
+
+/// Items to reveal, and items to hide within them.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rules {
+    #[serde(default)]
+    pub reveal: Vec<Item>,
+    #[serde(default)]
+    pub hide: Vec<Item>,
+}
+
+/// A part of every request or response.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Item {
+    /// All the data.
+    All,
+    /// The request line of requests, e.g. `GET /path HTTP/1.1`.
+    RequestLine,
+    /// The status line of responses, e.g. `HTTP/1.1 200 OK`.
+    StatusLine,
+    /// The start line and all headers.
+    Head,
+    /// Headers with this name, matched case-insensitively. Revealing reveals the whole header,
+    /// hiding hides only its value.
+    Header(String),
+    /// The member at a dot-separated path in JSON bodies, e.g. `accounts.0.balance`. Revealing
+    /// reveals its key and value, hiding hides only its value.
+    Json(String),
+    /// The key of the member at a JSON path, proving the member exists without its value.
+    JsonKey(String),
+    /// Matches of a regular expression, or of its capture groups if it has any.
+    Regex(String),
+}
+
+impl fmt::Display for Item {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Item::All => write!(f, "all data"),
+            Item::RequestLine => write!(f, "request line"),
+            Item::StatusLine => write!(f, "status line"),
+            Item::Head => write!(f, "head"),
+            Item::Header(name) => write!(f, "header {name}"),
+            Item::Json(path) => write!(f, "JSON path {path}"),
+            Item::JsonKey(path) => write!(f, "key of JSON path {path}"),
+            Item::Regex(pattern) => write!(f, "regex /{pattern}/"),
+        }
+    }
+}
+
+/// Whether the rules apply to the data sent to the server or received from it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+impl Direction {
+    fn messages(self) -> &'static str {
+        match self {
+            Direction::Sent => "requests",
+            Direction::Received => "responses",
+        }
+    }
+
+    fn message(self) -> &'static str {
+        match self {
+            Direction::Sent => "request",
+            Direction::Received => "response",
+        }
+    }
+}
+
+/// The parsed parts of a request or response.
+struct Message<'a> {
+    start_line: Range<usize>,
+    head: Range<usize>,
+    headers: &'a [Header],
+    body: Option<&'a Body>,
+}
+
+impl Message<'_> {
+    /// The range of the body, if it has a `Content-Encoding` other than `identity`.
+    fn compressed_body(&self) -> Option<Range<usize>> {
+        let compressed = self.headers.iter().any(|header| {
+            header
+                .name
+                .as_str()
+                .eq_ignore_ascii_case("Content-Encoding")
+                && !header
+                    .value
+                    .span()
+                    .as_bytes()
+                    .trim_ascii()
+                    .eq_ignore_ascii_case(b"identity")
+        });
+        compressed
+            .then(|| self.body.map(|body| range(body.span().indices())))
+            .flatten()
+    }
+}
+
+impl RevealSpec {
+    /// Reads a spec from a JSON file.
+    pub fn from_file(path: &std::path::Path) -> Result<Self, String> {
+        let spec = std::fs::read(path)
+            .map_err(|err| format!("Failed to read reveal spec {}: {err}", path.display()))?;
+        serde_json::from_slice(&spec)
+            .map_err(|err| format!("Invalid reveal spec {}: {err}", path.display()))
+    }
+
+    /// Hides every occurrence of `text` in the sent and received data.
+    pub fn hide_everywhere(&mut self, text: &str) {
+        let item = Item::Regex(regex::escape(text));
+        self.sent.hide.push(item.clone());
+        self.received.hide.push(item);
+    }
+}
+
+impl Rules {
+    /// Compiles the rules into the ranges of `transcript` to reveal.
+    pub fn compile(
+        &self,
+        transcript: &[u8],
+        direction: Direction,
+    ) -> Result<RangeSet<usize>, String> {
+        let requests;
+        let responses;
+        let messages = match direction {
+            Direction::Sent => {
+                requests = Requests::new_from_slice(transcript)
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|err| format!("Failed to parse the sent requests: {err}"))?;
+                requests
+                    .iter()
+                    .map(|request| Message {
+                        start_line: line(transcript, range(request.request.span().indices())),
+                        head: head(request.span().indices(), request.body.as_ref()),
+                        headers: &request.headers,
+                        body: request.body.as_ref(),
+                    })
+                    .collect::<Vec<_>>()
+            }
+            Direction::Received => {
+                responses = Responses::new_from_slice(transcript)
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|err| format!("Failed to parse the received responses: {err}"))?;
+                responses
+                    .iter()
+                    .map(|response| Message {
+                        start_line: line(transcript, range(response.status.span().indices())),
+                        head: head(response.span().indices(), response.body.as_ref()),
+                        headers: &response.headers,
+                        body: response.body.as_ref(),
+                    })
+                    .collect()
+            }
+        };
+
+        let compressed = messages
+            .iter()
+            .filter_map(Message::compressed_body)
+            .collect::<Vec<_>>();
+
+        let mut revealed = vec![false; transcript.len()];
+        for item in &self.reveal {
+            let ranges = item_ranges(item, transcript, &messages, direction, false)?;
+            // JSON paths cannot be looked up in compressed bodies, which hold them if anything.
+            let in_compressed =
+                matches!(item, Item::Json(_) | Item::JsonKey(_)) && !compressed.is_empty();
+            if ranges.is_empty() && !in_compressed {
+                return Err(format!(
+                    "{item} to reveal not found in the {}",
+                    direction.messages()
+                ));
+            }
+            for range in ranges {
+                revealed[range].fill(true);
+            }
+            if in_compressed {
+                for body in &compressed {
+                    revealed[body.clone()].fill(true);
+                }
+            }
+        }
+        for body in &compressed {
+            if revealed[body.clone()].contains(&true) {
+                revealed[body.clone()].fill(true);
+            }
+        }
+        // Hiding what is not there is harmless, so hidden items may match nothing.
+        for item in &self.hide {
+            for range in item_ranges(item, transcript, &messages, direction, true)? {
+                revealed[range].fill(false);
+            }
+        }
+
+        Ok(runs(&revealed))
+    }
+}
+
+fn item_ranges(
+    item: &Item,
+    transcript: &[u8],
+    messages: &[Message],
+    direction: Direction,
+    hide: bool,
+) -> Result<Vec<Range<usize>>, String> {
+    Ok(match item {
+        Item::All => std::iter::once(0..transcript.len()).collect(),
+        Item::RequestLine | Item::StatusLine => {
+            let expected = match direction {
+                Direction::Sent => Item::RequestLine,
+                Direction::Received => Item::StatusLine,
+            };
+            if *item != expected {
+                return Err(format!(
+                    "{item} cannot apply to the {}",
+                    direction.messages()
+                ));
+            }
+            messages.iter().map(|msg| msg.start_line.clone()).collect()
+        }
+        Item::Head => messages.iter().map(|msg| msg.head.clone()).collect(),
+        Item::Header(name) => messages
+            .iter()
+            .flat_map(|msg| msg.headers)
+            .filter(|header| header.name.as_str().eq_ignore_ascii_case(name))
+            .map(|header| {
+                if hide {
+                    range(header.value.span().indices())
+                } else {
+                    line(transcript, range(header.span().indices()))
+                }
+            })
+            .collect(),
+        Item::Json(path) | Item::JsonKey(path) => {
+            let mut ranges = Vec::new();
+            for (idx, body) in messages
+                .iter()
+                .enumerate()
+                .filter_map(|(idx, msg)| Some((idx, msg.body?)))
+            {
+                // Paths only apply to JSON bodies.
+                let Ok(mut root) = json::parse_slice(body.as_bytes()) else {
+                    continue;
+                };
+                root.offset(range(body.content.span().indices()).start);
+                let part = match (item, hide) {
+                    (Item::JsonKey(_), _) => Part::Key,
+                    (_, true) => Part::Value,
+                    (_, false) => Part::Pair,
+                };
+                match json_path::find(transcript, &root, path, part) {
+                    Ok(range) => ranges.push(range),
+                    Err(_) if hide => {}
+                    Err(err) => return Err(format!("In {} {idx}: {err}", direction.message())),
+                }
+            }
+            ranges
+        }
+        Item::Regex(pattern) => {
+            let regex =
+                Regex::new(pattern).map_err(|err| format!("Invalid regex {pattern:?}: {err}"))?;
+            regex
+                .captures_iter(transcript)
+                .flat_map(|captures| {
+                    let groups = captures.iter().skip(1).flatten().collect::<Vec<_>>();
+                    if groups.is_empty() {
+                        captures.get(0).into_iter().map(|m| m.range()).collect()
+                    } else {
+                        groups.into_iter().map(|m| m.range()).collect::<Vec<_>>()
+                    }
+                })
+                .collect()
+        }
+    })
+}
+
+/// The range spanned by `indices`.
+pub fn range(indices: &RangeSet<usize>) -> Range<usize> {
+    indices.min().unwrap_or_default()..indices.max().map_or(0, |max| max + 1)
+}
+
+/// Extends a line over its line break, if it does not include it already.
+fn line(transcript: &[u8], line: Range<usize>) -> Range<usize> {
+    if transcript[line.end..].starts_with(b"\r\n") && !transcript[line.clone()].ends_with(b"\n") {
+        line.start..line.end + 2
+    } else {
+        line
+    }
+}
+
+/// The range of the start line and headers of a message.
+fn head(message: &RangeSet<usize>, body: Option<&Body>) -> Range<usize> {
+    let message = range(message);
+    match body {
+        Some(body) => message.start..range(body.span().indices()).start,
+        None => message,
+    }
+}
+
+/// Maximal runs of `true`.
+fn runs(mask: &[bool]) -> RangeSet<usize> {
+    let mut ranges = Vec::new();
+    let mut start = None;
+    for (idx, &set) in mask.iter().chain([&false]).enumerate() {
+        match (set, start) {
+            (true, None) => start = Some(idx),
+            (false, Some(run)) => {
+                ranges.push(run..idx);
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    ranges.into()
+}
@@ -0,0 +1,67 @@
+//! Connections from the prover to the TLS server and the verifier.
+use async_tungstenite::tokio::client_async_tls;
+use hyper::Uri;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+};
+use tracing::{info, warn};
+use ws_stream_tungstenite::WsStream;
+
+use crate::proxy::Proxy;
+
+/// A byte stream to the TLS server, however it is reached.
+pub trait ServerSocket: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> ServerSocket for T {}
+
+/// Opens a TCP connection to `host:port`, through `proxy` if given.
+pub async fn connect_tcp(host: &str, port: u16, proxy: Option<&Proxy>) -> TcpStream {
+    match proxy {
+        Some(proxy) => {
+            info!("Connecting to {host}:{port} through proxy {proxy}");
+            proxy.connect(host, port).await.unwrap_or_else(|err| {
+                panic!("Failed to connect to {host}:{port} through proxy {proxy}: {err}")
+            })
+        }
+        None => TcpStream::connect((host, port))
+            .await
+            .unwrap_or_else(|err| panic!("Failed to connect to {host}:{port}: {err}")),
+    }
+}
+
+/// Connects to the server directly, or through a WebSocket-to-TCP proxy as the browser prover
+/// does. The proxy is told the server's host in its `token` query parameter and connects to
+/// port 443. Either connection goes through `proxy` if given.
+pub async fn connect_server(
+    host: &str,
+    port: u16,
+    websocket_proxy: Option<&Uri>,
+    proxy: Option<&Proxy>,
+) -> Box<dyn ServerSocket> {
+    let Some(websocket_proxy) = websocket_proxy else {
+        return Box::new(connect_tcp(host, port, proxy).await);
+    };
+
+    if port != 443 {
+        warn!("The websocket proxy connects to port 443, not {port}");
+    }
+    let separator = if websocket_proxy.query().is_some() {
+        '&'
+    } else {
+        '?'
+    };
+    let url = format!("{websocket_proxy}{separator}token={host}");
+    info!("Connecting to {host} through websocket proxy {websocket_proxy}");
+    let proxy_host = websocket_proxy.host().unwrap();
+    let default_port = match websocket_proxy.scheme_str() {
+        Some("wss") => 443,
+        _ => 80,
+    };
+    let proxy_port = websocket_proxy.port_u16().unwrap_or(default_port);
+    let socket = connect_tcp(proxy_host, proxy_port, proxy).await;
+    let (stream, _) = client_async_tls(url, socket).await.unwrap_or_else(|err| {
+        panic!("Failed to connect to websocket proxy {websocket_proxy}: {err}")
+    });
+    Box::new(WsStream::new(stream))
+}
@@ -294,6 +294,22 @@ impl<F> WebSocketUpgrade<F> {
         self
     }
 
+    /// NOTARY_MODIFICATION: Return the protocols offered by the client in the `Sec-WebSocket-Protocol` header.
+    pub fn requested_protocols(&self) -> impl Iterator<Item = &str> {
+        self.sec_websocket_protocol
+            .as_ref()
+            .and_then(|p| p.to_str().ok())
+            .into_iter()
+            .flat_map(|p| p.split(','))
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+    }
+
+    /// NOTARY_MODIFICATION: Return the protocol chosen by [`protocols`](Self::protocols), if any.
+    pub fn selected_protocol(&self) -> Option<&HeaderValue> {
+        self.protocol.as_ref()
+    }
+
     /// Provide a callback to call if upgrading the connection fails.
     ///
     /// The connection upgrade is performed in a background task. If that fails this callback
@@ -0,0 +1,90 @@
+//! Verifies a presentation in tlsn-js's `PresentationJSON` format against trusted notary keys,
+//! printing what it proves. Exits with a non-zero status if the presentation is invalid or not
+//! signed by a trusted notary.
+use clap::Parser;
+use eyre::eyre;
+use interactive_networked_verifier::{
+    presentation::{NotaryKey, PresentationJson},
+    render::{segments, Rendering},
+    transcript::Revealed,
+};
+use std::{io::Read, path::PathBuf};
+
+/// Verifies a tlsn-js presentation.
+#[derive(Debug, Parser)]
+#[command(about)]
+struct Args {
+    /// The `PresentationJSON` file, or `-` for stdin
+    presentation: PathBuf,
+
+    /// Trusted notary public key, in hex as tlsn-js's `NotaryServer.publicKey()` returns it, or
+    /// as PEM; may be repeated
+    #[arg(long = "notary-key", value_name = "KEY")]
+    notary_keys: Vec<String>,
+
+    /// File holding a trusted notary public key, in hex or PEM; may be repeated
+    #[arg(long = "notary-key-file", value_name = "PATH")]
+    notary_key_files: Vec<PathBuf>,
+}
+
+fn main() -> Result<(), eyre::ErrReport> {
+    let args = Args::parse();
+
+    let mut trusted = args
+        .notary_keys
+        .iter()
+        .map(|key| NotaryKey::parse(key))
+        .collect::<Result<Vec<_>, _>>()?;
+    for path in &args.notary_key_files {
+        let key = std::fs::read_to_string(path)
+            .map_err(|err| eyre!("Failed to read {}: {err}", path.display()))?;
+        trusted.push(NotaryKey::parse(&key)?);
+    }
+    if trusted.is_empty() {
+        return Err(eyre!(
+            "No trusted notary keys, pass --notary-key or --notary-key-file"
+        ));
+    }
+
+    let json = if args.presentation.as_os_str() == "-" {
+        let mut json = String::new();
+        std::io::stdin().read_to_string(&mut json)?;
+        json
+    } else {
+        std::fs::read_to_string(&args.presentation)
+            .map_err(|err| eyre!("Failed to read {}: {err}", args.presentation.display()))?
+    };
+    let presentation = PresentationJson::from_json(&json)?;
+    let verified = presentation.verify(&trusted)?;
+
+    println!(
+        "Verified presentation signed by notary {}",
+        verified.notary_key
+    );
+    if let Some(url) = &presentation.meta.notary_url {
+        println!("Notary URL: {url}");
+    }
+    println!(
+        "Server name: {}",
+        verified.server_name.as_deref().unwrap_or("not revealed")
+    );
+    println!(
+        "Connection time: {}",
+        httpdate::fmt_http_date(verified.connection_time)
+    );
+    match &verified.transcript {
+        Some(transcript) => {
+            let rendering = Rendering::default();
+            println!(
+                "Sent data:\n{}",
+                rendering.render(&segments(&Revealed::sent(transcript)))
+            );
+            println!(
+                "Received data:\n{}",
+                rendering.render(&segments(&Revealed::received(transcript)))
+            );
+        }
+        None => println!("No transcript data was revealed"),
+    }
+    Ok(())
+}
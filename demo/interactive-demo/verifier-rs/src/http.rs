@@ -0,0 +1,752 @@
+//! Redaction-aware parsing of the HTTP messages in a revealed transcript.
+//!
+//! Only revealed bytes are trusted for the structure of a message: a header is parsed only if
+//! its line starts right after a revealed CRLF, so a prover cannot redact the start of a header
+//! name to make it read as another header.
+//!
+//! Field values and request targets cannot hold line breaks, so a redacted run inside a header
+//! value or a start line part is taken to end at the revealed delimiters around it. A redacted
+//! header name or line, however, could hide the empty line that really ends the head, making
+//! body bytes read as headers. So everything from such a line on is ignored, and its message is
+//! taken to run to the end of the data, with no body.
+//!
+//! Chunked bodies are de-chunked into their logical content, which keeps the transcript offset
+//! of every byte, so checks on the body can still report the original byte ranges. Bodies with a
+//! `Content-Encoding` are decompressed if fully revealed.
+use eyre::eyre;
+use std::{io::Read, ops::Range};
+
+use crate::{json::Json, transcript::Revealed};
+
+/// An HTTP header. Names are lowercased.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub name: String,
+    /// The value with surrounding whitespace trimmed, or `None` if any of it is redacted.
+    pub value: Option<Vec<u8>>,
+    /// Span of the whole header line in the transcript, without the CRLF.
+    pub span: Range<usize>,
+}
+
+impl Header {
+    /// The value as a string, if revealed and valid UTF-8.
+    pub fn value_str(&self) -> Option<&str> {
+        self.value
+            .as_deref()
+            .and_then(|value| std::str::from_utf8(value).ok())
+    }
+}
+
+/// The logical body of an HTTP message, i.e. de-chunked if sent with chunked encoding.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Body {
+    /// The body bytes. Redacted bytes are meaningless.
+    pub data: Vec<u8>,
+    /// Whether each byte of `data` is revealed.
+    pub revealed: Vec<bool>,
+    /// Offset in the transcript of each byte of `data`. Empty if decompressed.
+    pub offsets: Vec<usize>,
+    /// Transcript ranges of the compressed body, if decompressed.
+    pub decoded_from: Option<Vec<Range<usize>>>,
+}
+
+impl Body {
+    /// Concatenates the transcript `spans` holding the body.
+    fn new(revealed: &Revealed<'_>, spans: &[Range<usize>]) -> Self {
+        let offsets = spans.iter().flat_map(Range::clone).collect::<Vec<_>>();
+        Self {
+            data: offsets.iter().map(|&idx| revealed.data()[idx]).collect(),
+            revealed: offsets
+                .iter()
+                .map(|&idx| revealed.is_revealed(idx))
+                .collect(),
+            offsets,
+            decoded_from: None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Whether every byte in `range` of the body is revealed.
+    pub fn is_range_revealed(&self, range: Range<usize>) -> bool {
+        !self.revealed[range].contains(&false)
+    }
+
+    pub fn is_fully_revealed(&self) -> bool {
+        !self.revealed.contains(&false)
+    }
+
+    /// The body as a string, if fully revealed and valid UTF-8.
+    pub fn as_str(&self) -> Option<&str> {
+        self.is_fully_revealed()
+            .then(|| std::str::from_utf8(&self.data).ok())
+            .flatten()
+    }
+
+    /// Parses the body as JSON, tolerating redacted values.
+    pub fn json(&self) -> Result<Json, eyre::ErrReport> {
+        Json::parse(&self.data, &self.revealed)
+    }
+
+    /// Maps a range of the body to the transcript ranges it was read from. Ranges of a
+    /// decompressed body map to the whole compressed body.
+    pub fn transcript_ranges(&self, range: Range<usize>) -> Vec<Range<usize>> {
+        if let Some(ranges) = &self.decoded_from {
+            return ranges.clone();
+        }
+        let mut ranges: Vec<Range<usize>> = Vec::new();
+        for &offset in
+            &self.offsets[range.start.min(self.offsets.len())..range.end.min(self.offsets.len())]
+        {
+            match ranges.last_mut() {
+                Some(last) if last.end == offset => last.end += 1,
+                _ => ranges.push(offset..offset + 1),
+            }
+        }
+        ranges
+    }
+}
+
+/// An HTTP request sent by the prover.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Request {
+    /// The method, or `None` if redacted.
+    pub method: Option<String>,
+    /// The request target, or `None` if any of it is redacted.
+    pub target: Option<String>,
+    pub version: Option<String>,
+    pub headers: Vec<Header>,
+    pub body: Option<Body>,
+    /// Span of the whole message in the transcript.
+    pub span: Range<usize>,
+}
+
+/// An HTTP response received by the prover.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Response {
+    pub version: Option<String>,
+    /// The status code, or `None` if redacted.
+    pub status: Option<u16>,
+    pub reason: Option<String>,
+    pub headers: Vec<Header>,
+    pub body: Option<Body>,
+    /// Span of the whole message in the transcript.
+    pub span: Range<usize>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&Header> {
+        find_header(&self.headers, name)
+    }
+}
+
+impl Response {
+    pub fn header(&self, name: &str) -> Option<&Header> {
+        find_header(&self.headers, name)
+    }
+}
+
+fn find_header<'a>(headers: &'a [Header], name: &str) -> Option<&'a Header> {
+    headers
+        .iter()
+        .find(|header| header.name.eq_ignore_ascii_case(name))
+}
+
+/// Parses the request starting at `start` in the sent data.
+pub fn parse_request(sent: &Revealed<'_>, start: usize) -> Result<Request, eyre::ErrReport> {
+    let head = parse_head(sent, start)?;
+    let [method, target, version] = head.start_line;
+
+    // Requests without a length have no body.
+    let (spans, end) = if head.redacted {
+        (Vec::new(), sent.len())
+    } else {
+        body_spans(sent, &head.headers, head.end, head.end)
+            .map_err(|err| eyre!("Invalid request body: {err}"))?
+    };
+
+    Ok(Request {
+        method,
+        target,
+        version,
+        body: decode_body(&head.headers, sent, &spans)
+            .map_err(|err| eyre!("Invalid request body: {err}"))?,
+        headers: head.headers,
+        span: start..end,
+    })
+}
+
+/// Parses the response starting at `start` in the received data.
+pub fn parse_response(received: &Revealed<'_>, start: usize) -> Result<Response, eyre::ErrReport> {
+    parse_response_to(received, start, None)
+}
+
+/// A request and the response to it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Exchange {
+    pub request: Request,
+    pub response: Response,
+}
+
+/// Splits the transcript of a keep-alive connection into its request/response exchanges, in
+/// the order they were made. Interim `1xx` responses are skipped.
+pub fn parse_exchanges(
+    sent: &Revealed<'_>,
+    received: &Revealed<'_>,
+) -> Result<Vec<Exchange>, eyre::ErrReport> {
+    let mut exchanges = Vec::new();
+    let mut sent_start = 0;
+    let mut received_start = 0;
+    while sent_start < sent.len() {
+        let idx = exchanges.len();
+        let request =
+            parse_request(sent, sent_start).map_err(|err| eyre!("Exchange {idx}: {err}"))?;
+        let response = loop {
+            if received_start >= received.len() {
+                return Err(eyre!("Exchange {idx}: request has no response"));
+            }
+            let response = parse_response_to(received, received_start, request.method.as_deref())
+                .map_err(|err| eyre!("Exchange {idx}: {err}"))?;
+            received_start = response.span.end;
+            // 101 Switching Protocols is final, the connection stops speaking HTTP after it.
+            if !matches!(response.status, Some(100 | 102..=199)) {
+                break response;
+            }
+        };
+
+        sent_start = request.span.end;
+        exchanges.push(Exchange { request, response });
+    }
+    Ok(exchanges)
+}
+
+/// Parses a response to a request with `method`, which is needed to tell whether it has a body.
+fn parse_response_to(
+    received: &Revealed<'_>,
+    start: usize,
+    method: Option<&str>,
+) -> Result<Response, eyre::ErrReport> {
+    let head = parse_head(received, start)?;
+    let [version, status, reason] = head.start_line;
+    let status = status
+        .map(|status| {
+            status
+                .parse::<u16>()
+                .map_err(|_| eyre!("Invalid status code {status:?}"))
+        })
+        .transpose()?;
+
+    // Responses without a length last until the connection is closed.
+    let bodiless = method == Some("HEAD") || matches!(status, Some(100..=199 | 204 | 304));
+    let (spans, end) = if head.redacted {
+        (Vec::new(), received.len())
+    } else if bodiless {
+        (Vec::new(), head.end)
+    } else {
+        body_spans(received, &head.headers, head.end, received.len())
+            .map_err(|err| eyre!("Invalid response body: {err}"))?
+    };
+
+    Ok(Response {
+        version,
+        status,
+        reason,
+        body: decode_body(&head.headers, received, &spans)
+            .map_err(|err| eyre!("Invalid response body: {err}"))?,
+        headers: head.headers,
+        span: start..end,
+    })
+}
+
+struct Head {
+    /// The three parts of the start line, `None` where redacted.
+    start_line: [Option<String>; 3],
+    headers: Vec<Header>,
+    /// Offset right after the empty line ending the head.
+    end: usize,
+    /// Whether a header line of the head has a redacted name, so that its end and what follows
+    /// that line cannot be trusted.
+    redacted: bool,
+}
+
+fn parse_head(data: &Revealed<'_>, start: usize) -> Result<Head, eyre::ErrReport> {
+    let head_end = data
+        .find(b"\r\n\r\n", start)
+        .ok_or_else(|| eyre!("End of HTTP headers is missing or redacted"))?;
+
+    let mut lines = Vec::new();
+    let mut line_start = start;
+    while line_start < head_end + 2 {
+        let line_end = data
+            .find(b"\r\n", line_start)
+            .expect("head ends with a revealed CRLF");
+        lines.push(line_start..line_end);
+        line_start = line_end + 2;
+    }
+
+    // A redacted header value is bounded by the revealed CRLF after it, but a redacted name or
+    // line may hide the real end of the head, so nothing from such a line on is trusted.
+    let mut lines = lines.into_iter();
+    let start_line = lines.next().expect("head has a start line");
+    let start_line = parse_start_line(data, start_line)?;
+
+    let mut headers = Vec::new();
+    let mut redacted = false;
+    for line in lines {
+        match parse_header(data, line.clone()) {
+            Some(header) => headers.push(header),
+            None if data.get(line).is_some() => {}
+            None => {
+                redacted = true;
+                break;
+            }
+        }
+    }
+
+    Ok(Head {
+        start_line,
+        headers,
+        end: head_end + 4,
+        redacted,
+    })
+}
+
+fn parse_start_line(
+    data: &Revealed<'_>,
+    line: Range<usize>,
+) -> Result<[Option<String>; 3], eyre::ErrReport> {
+    let first_space = data
+        .find(b" ", line.start)
+        .filter(|&idx| idx < line.end)
+        .ok_or_else(|| eyre!("HTTP start line is malformed or redacted"))?;
+    let second_space = data
+        .find(b" ", first_space + 1)
+        .filter(|&idx| idx < line.end)
+        .ok_or_else(|| eyre!("HTTP start line is malformed or redacted"))?;
+
+    let part = |range: Range<usize>| {
+        data.get(range)
+            .and_then(|bytes| std::str::from_utf8(bytes).ok())
+            .map(str::to_string)
+    };
+
+    Ok([
+        part(line.start..first_space),
+        part(first_space + 1..second_space),
+        part(second_space + 1..line.end),
+    ])
+}
+
+fn parse_header(data: &Revealed<'_>, line: Range<usize>) -> Option<Header> {
+    let colon = data.find(b":", line.start).filter(|&idx| idx < line.end)?;
+    let name = data.get(line.start..colon)?;
+    let name = std::str::from_utf8(name).ok()?.trim().to_ascii_lowercase();
+
+    let mut value = colon + 1..line.end;
+    while value.start < value.end
+        && data.is_revealed(value.start)
+        && data.data()[value.start].is_ascii_whitespace()
+    {
+        value.start += 1;
+    }
+    while value.start < value.end
+        && data.is_revealed(value.end - 1)
+        && data.data()[value.end - 1].is_ascii_whitespace()
+    {
+        value.end -= 1;
+    }
+
+    Some(Header {
+        name,
+        value: data.get(value).map(<[u8]>::to_vec),
+        span: line,
+    })
+}
+
+/// Returns the transcript spans holding the body starting at `start` and the end of the message.
+/// Bodies without a length end at `default_end`.
+fn body_spans(
+    data: &Revealed<'_>,
+    headers: &[Header],
+    start: usize,
+    default_end: usize,
+) -> Result<(Vec<Range<usize>>, usize), eyre::ErrReport> {
+    if is_chunked(headers)? {
+        return chunk_spans(data, start);
+    }
+
+    let end = match content_length(headers)? {
+        Some(length) => start
+            .checked_add(length)
+            .ok_or_else(|| eyre!("Content-Length too large"))?,
+        None => default_end,
+    };
+    if end > data.len() {
+        return Err(eyre!("body is truncated"));
+    }
+    let spans = (end > start).then_some(start..end).into_iter().collect();
+    Ok((spans, end))
+}
+
+/// Parses a chunked body starting at `start`. Chunk sizes and delimiters must be revealed, chunk
+/// data may be redacted.
+fn chunk_spans(
+    data: &Revealed<'_>,
+    start: usize,
+) -> Result<(Vec<Range<usize>>, usize), eyre::ErrReport> {
+    let mut spans = Vec::new();
+    let mut pos = start;
+    loop {
+        let line_end = data
+            .find(b"\r\n", pos)
+            .ok_or_else(|| eyre!("chunk size at {pos} is truncated or redacted"))?;
+        let line = data
+            .get(pos..line_end)
+            .ok_or_else(|| eyre!("chunk size at {pos} is redacted"))?;
+        // Chunk extensions follow a ';'.
+        let size = line.split(|&byte| byte == b';').next().unwrap_or_default();
+        let size = std::str::from_utf8(size)
+            .ok()
+            .and_then(|size| usize::from_str_radix(size.trim(), 16).ok())
+            .ok_or_else(|| eyre!("invalid chunk size at {pos}"))?;
+        let size_pos = pos;
+        pos = line_end + 2;
+
+        if size == 0 {
+            break;
+        }
+        let delimiter_end = pos
+            .checked_add(size)
+            .and_then(|chunk_end| chunk_end.checked_add(2))
+            .ok_or_else(|| eyre!("chunk size too large at {size_pos}"))?;
+        let chunk_end = delimiter_end - 2;
+        if data.get(chunk_end..delimiter_end) != Some(&b"\r\n"[..]) {
+            return Err(eyre!(
+                "chunk at {pos} is truncated or its delimiter is redacted"
+            ));
+        }
+        spans.push(pos..chunk_end);
+        pos = delimiter_end;
+    }
+
+    // Skip the trailer fields, up to the empty line ending the body.
+    loop {
+        let line_end = data
+            .find(b"\r\n", pos)
+            .ok_or_else(|| eyre!("end of chunked body is truncated or redacted"))?;
+        if data.get(pos..line_end).is_none() {
+            return Err(eyre!("chunk trailer at {pos} is redacted"));
+        }
+        let empty = line_end == pos;
+        pos = line_end + 2;
+        if empty {
+            return Ok((spans, pos));
+        }
+    }
+}
+
+/// Limit on the size of a decompressed body.
+const MAX_DECODED_BODY: u64 = 1 << 24;
+
+/// Reads the body held in `spans`, decompressing it as given by `Content-Encoding`.
+///
+/// A compressed body is opaque unless fully revealed, so a partially revealed one is rejected.
+/// A fully redacted one is kept as is, since there is nothing to check in it anyway.
+fn decode_body(
+    headers: &[Header],
+    data: &Revealed<'_>,
+    spans: &[Range<usize>],
+) -> Result<Option<Body>, eyre::ErrReport> {
+    if spans.is_empty() {
+        return Ok(None);
+    }
+    let body = Body::new(data, spans);
+    let Some(header) = find_header(headers, "content-encoding") else {
+        return Ok(Some(body));
+    };
+    let encodings = header
+        .value_str()
+        .ok_or_else(|| eyre!("Content-Encoding header is redacted"))?;
+    let encodings = encodings
+        .split(',')
+        .map(|encoding| encoding.trim().to_ascii_lowercase())
+        .filter(|encoding| !encoding.is_empty() && encoding != "identity")
+        .collect::<Vec<_>>();
+    if encodings.is_empty() || !body.revealed.contains(&true) {
+        return Ok(Some(body));
+    }
+    if !body.is_fully_revealed() {
+        return Err(eyre!(
+            "body is {} compressed and only partially revealed, so it cannot be checked",
+            encodings.join(", ")
+        ));
+    }
+
+    // Encodings are listed in the order they were applied.
+    let mut decoded = body.data;
+    for encoding in encodings.iter().rev() {
+        decoded = decompress(encoding, &decoded)?;
+    }
+
+    Ok(Some(Body {
+        revealed: vec![true; decoded.len()],
+        data: decoded,
+        offsets: Vec::new(),
+        decoded_from: Some(spans.to_vec()),
+    }))
+}
+
+fn decompress(encoding: &str, data: &[u8]) -> Result<Vec<u8>, eyre::ErrReport> {
+    let reader: Box<dyn Read + '_> = match encoding {
+        "gzip" | "x-gzip" => Box::new(flate2::read::MultiGzDecoder::new(data)),
+        "deflate" => Box::new(flate2::read::ZlibDecoder::new(data)),
+        "br" => Box::new(brotli_decompressor::Decompressor::new(data, 4096)),
+        encoding => return Err(eyre!("unsupported Content-Encoding {encoding:?}")),
+    };
+
+    let mut decoded = Vec::new();
+    reader
+        .take(MAX_DECODED_BODY + 1)
+        .read_to_end(&mut decoded)
+        .map_err(|err| eyre!("failed to decompress {encoding} body: {err}"))?;
+    if decoded.len() as u64 > MAX_DECODED_BODY {
+        return Err(eyre!("decompressed body exceeds {MAX_DECODED_BODY} bytes"));
+    }
+    Ok(decoded)
+}
+
+fn is_chunked(headers: &[Header]) -> Result<bool, eyre::ErrReport> {
+    let Some(header) = find_header(headers, "transfer-encoding") else {
+        return Ok(false);
+    };
+    let encodings = header
+        .value_str()
+        .ok_or_else(|| eyre!("Transfer-Encoding header is redacted"))?;
+    // Chunked is the last encoding whenever it is used.
+    Ok(encodings
+        .rsplit(',')
+        .next()
+        .is_some_and(|encoding| encoding.trim().eq_ignore_ascii_case("chunked")))
+}
+
+fn content_length(headers: &[Header]) -> Result<Option<usize>, eyre::ErrReport> {
+    let Some(header) = find_header(headers, "content-length") else {
+        return Ok(None);
+    };
+    let length = header
+        .value_str()
+        .ok_or_else(|| eyre!("Content-Length header is redacted"))?;
+    length
+        .parse::<usize>()
+        .map(Some)
+        .map_err(|_| eyre!("Invalid Content-Length {length:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a transcript view, treating `#` as a redacted byte.
+    fn revealed(data: &str) -> Revealed<'_> {
+        let authed = data
+            .bytes()
+            .enumerate()
+            .filter(|(_, byte)| *byte != b'#')
+            .map(|(idx, _)| idx..idx + 1);
+        Revealed::new(data.as_bytes(), authed)
+    }
+
+    #[test]
+    fn parses_request_with_redacted_header() {
+        let sent = "GET /api HTTP/1.1\r\nHost: example.com\r\nAuthorization: ######\r\nAccept: */*\r\n\r\n";
+        let request = parse_request(&revealed(sent), 0).unwrap();
+
+        assert_eq!(request.method.as_deref(), Some("GET"));
+        assert_eq!(request.target.as_deref(), Some("/api"));
+        assert_eq!(request.version.as_deref(), Some("HTTP/1.1"));
+        assert_eq!(
+            request.header("host").unwrap().value_str(),
+            Some("example.com")
+        );
+        assert_eq!(request.header("authorization").unwrap().value, None);
+        // The redacted value ends at the revealed CRLF after it.
+        assert_eq!(request.header("accept").unwrap().value_str(), Some("*/*"));
+        assert_eq!(request.headers.len(), 3);
+        assert!(request.body.is_none());
+        assert_eq!(request.span, 0..sent.len());
+
+        let sent = "GET /api?key=#### HTTP/1.1\r\nHost: example.com\r\n\r\n";
+        let request = parse_request(&revealed(sent), 0).unwrap();
+        assert_eq!(request.method.as_deref(), Some("GET"));
+        assert_eq!(request.target, None);
+        assert_eq!(request.version.as_deref(), Some("HTTP/1.1"));
+        assert!(request.header("host").is_some());
+    }
+
+    #[test]
+    fn does_not_read_body_after_redacted_head_end_as_headers() {
+        // The real head may end inside the redacted line, the "headers" after it are body bytes.
+        let received =
+            "HTTP/1.1 200 OK\r\nSet-Cookie: a\r\n############\r\nX-Verified: yes\r\n\r\n";
+        let response = parse_response(&revealed(received), 0).unwrap();
+
+        assert_eq!(response.status, Some(200));
+        assert_eq!(
+            response.header("set-cookie").unwrap().value_str(),
+            Some("a")
+        );
+        assert!(response.header("x-verified").is_none());
+        assert_eq!(response.span, 0..received.len());
+    }
+
+    #[test]
+    fn splits_exchanges_after_redacted_header_values() {
+        // Logging in, then fetching with the session cookie redacted.
+        let sent = "POST /login HTTP/1.1\r\nAuthorization: ######\r\nContent-Length: 0\r\n\r\nGET /api/me HTTP/1.1\r\nCookie: ########\r\n\r\n";
+        let received = "HTTP/1.1 204 No Content\r\nSet-Cookie: ########\r\n\r\nHTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n{}";
+        let exchanges = parse_exchanges(&revealed(sent), &revealed(received)).unwrap();
+
+        assert_eq!(exchanges.len(), 2);
+        let policy = crate::policy::NthExchange::new(1)
+            .request("GET", "/api/me")
+            .status(200);
+        assert!(policy.check_exchanges(&exchanges).passed);
+    }
+
+    #[test]
+    fn rejects_overflowing_body_lengths() {
+        for size in ["ffffffffffffffff", "fffffffffffffffe", "fffffffffffffff0"] {
+            let received = format!(
+                "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n{size}\r\nab\r\n0\r\n\r\n"
+            );
+            assert!(parse_response(&revealed(&received), 0).is_err());
+        }
+        let err = parse_response(
+            &revealed("HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nffffffffffffffff\r\n"),
+            0,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("chunk size too large"), "{err}");
+
+        let received = format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\nab",
+            usize::MAX
+        );
+        let err = parse_response(&revealed(&received), 0).unwrap_err();
+        assert!(err.to_string().contains("too large"), "{err}");
+    }
+
+    #[test]
+    fn skips_interim_responses() {
+        let sent = "POST /a HTTP/1.1\r\nContent-Length: 2\r\n\r\n{}GET /b HTTP/1.1\r\n\r\n";
+        let received = "HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 201 Created\r\nContent-Length: 0\r\n\r\nHTTP/1.1 103 Early Hints\r\nLink: </a.css>\r\n\r\nHTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok";
+        let exchanges = parse_exchanges(&revealed(sent), &revealed(received)).unwrap();
+
+        let statuses = exchanges
+            .iter()
+            .map(|exchange| exchange.response.status.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(statuses, [201, 200]);
+        assert_eq!(
+            exchanges[1].response.body.as_ref().unwrap().as_str(),
+            Some("ok")
+        );
+
+        let interim_only = "HTTP/1.1 100 Continue\r\n\r\n";
+        let err = parse_exchanges(&revealed(&sent[..41]), &revealed(interim_only)).unwrap_err();
+        assert!(err.to_string().contains("no response"), "{err}");
+    }
+
+    #[test]
+    fn does_not_trust_partially_redacted_header_names() {
+        let sent = "GET / HTTP/1.1\r\n####Host: evil.com\r\n\r\n";
+        let request = parse_request(&revealed(sent), 0).unwrap();
+
+        assert!(request.header("host").is_none());
+    }
+
+    #[test]
+    fn parses_response_body() {
+        let received = "HTTP/1.1 200 OK\r\nContent-Length: 13\r\n\r\n{\"a\": \"###\"}\n";
+        let response = parse_response(&revealed(received), 0).unwrap();
+
+        assert_eq!(response.status, Some(200));
+        assert_eq!(response.reason.as_deref(), Some("OK"));
+        let body = response.body.unwrap();
+        assert!(!body.is_fully_revealed());
+        assert_eq!(body.transcript_ranges(7..10), vec![46..49]);
+        assert!(body.json().unwrap().lookup("a") != crate::json::Lookup::Missing);
+    }
+
+    #[test]
+    fn dechunks_body_with_offsets() {
+        let received = "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n7;ext=1\r\n{\"a\": \"\r\n5\r\n###\"}\r\n0\r\nX-Trailer: 1\r\n\r\n";
+        let response = parse_response(&revealed(received), 0).unwrap();
+
+        assert_eq!(response.span, 0..received.len());
+        let body = response.body.unwrap();
+        assert_eq!(body.data, b"{\"a\": \"###\"}");
+        // The redacted value spans both chunks, but maps back to the second one.
+        assert_eq!(body.transcript_ranges(6..11), vec![62..63, 68..72]);
+        assert!(matches!(
+            body.json().unwrap().lookup("a"),
+            crate::json::Lookup::Redacted(_)
+        ));
+
+        let redacted_size = received.replacen("5\r\n", "#\r\n", 1);
+        assert!(parse_response(&revealed(&redacted_size), 0).is_err());
+    }
+
+    #[test]
+    fn splits_exchanges() {
+        let sent = "POST /login HTTP/1.1\r\nContent-Length: 4\r\n\r\n####HEAD /a HTTP/1.1\r\n\r\nGET /api/me HTTP/1.1\r\n\r\n";
+        let received = "HTTP/1.1 204 No Content\r\n\r\nHTTP/1.1 200 OK\r\nContent-Length: 9\r\n\r\nHTTP/1.1 200 OK\r\n\r\n{\"id\": 1}";
+        let exchanges = parse_exchanges(&revealed(sent), &revealed(received)).unwrap();
+
+        let targets = exchanges
+            .iter()
+            .map(|exchange| exchange.request.target.as_deref().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(targets, ["/login", "/a", "/api/me"]);
+        assert!(exchanges[1].response.body.is_none());
+        assert_eq!(
+            exchanges[2].response.body.as_ref().unwrap().as_str(),
+            Some(r#"{"id": 1}"#)
+        );
+
+        let missing = parse_exchanges(&revealed(sent), &revealed(&received[..27]));
+        assert!(missing.unwrap_err().to_string().contains("no response"));
+    }
+
+    #[test]
+    fn decompresses_fully_revealed_bodies() {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(br#"{"a": 1}"#).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let head = format!(
+            "HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\nContent-Length: {}\r\n\r\n",
+            compressed.len()
+        );
+        let data = [head.as_bytes(), &compressed].concat();
+
+        let response =
+            parse_response(&Revealed::new(&data, std::iter::once(0..data.len())), 0).unwrap();
+        let body = response.body.unwrap();
+        assert_eq!(body.as_str(), Some(r#"{"a": 1}"#));
+        assert_eq!(body.transcript_ranges(0..1), vec![head.len()..data.len()]);
+
+        // Partially revealed compressed bodies cannot be checked.
+        let partial = Revealed::new(&data, std::iter::once(0..data.len() - 1));
+        let err = parse_response(&partial, 0).unwrap_err();
+        assert!(err.to_string().contains("partially revealed"), "{err}");
+    }
+}
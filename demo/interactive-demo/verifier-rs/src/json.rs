@@ -0,0 +1,491 @@
+//! Redaction-tolerant JSON parsing.
+//!
+//! Provers usually reveal only parts of a JSON body. As long as the structure around the
+//! redacted parts is revealed, e.g. `{"id": 42, "token": "XXXXXX"}` with only the token value
+//! redacted, the body still parses: redacted values become [`JsonKind::Redacted`] and
+//! redacted members keep their place with an unknown key. The bytes of a redacted value may
+//! also hide further members, so objects holding one may have members that were not parsed.
+use eyre::eyre;
+use std::ops::Range;
+
+/// The deepest nesting of arrays and objects parsed, so that hostile bodies such as a long run of
+/// `[` cannot overflow the stack.
+pub const MAX_DEPTH: usize = 128;
+
+/// A JSON value with its span in the parsed input.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Json {
+    pub kind: JsonKind,
+    pub span: Range<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum JsonKind {
+    Null,
+    Bool(bool),
+    /// The number literal, kept as is so no precision is lost.
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<Member>),
+    /// A value that is fully or partially redacted.
+    Redacted,
+}
+
+/// A member of a JSON object.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Member {
+    /// The key, or `None` if it is redacted.
+    pub key: Option<String>,
+    pub value: Json,
+}
+
+/// Result of looking up a path in a JSON value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Lookup<'a> {
+    Found(&'a Json),
+    /// The value, or one of its parents, is redacted.
+    Redacted(&'a Json),
+    /// The value does not exist, and no redacted member could be hiding it.
+    Missing,
+}
+
+impl Json {
+    /// Parses `input`, where `revealed[i]` tells whether `input[i]` is revealed.
+    pub fn parse(input: &[u8], revealed: &[bool]) -> Result<Self, eyre::ErrReport> {
+        let mut parser = Parser {
+            input,
+            revealed,
+            pos: 0,
+            depth: 0,
+        };
+        parser.skip_whitespace();
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.pos < input.len() {
+            return Err(eyre!("Unexpected data after JSON value at {}", parser.pos));
+        }
+        Ok(value)
+    }
+
+    /// Looks up a dot-separated path, where numeric segments index arrays, e.g.
+    /// `accounts.0.balance`. An empty path returns the value itself.
+    pub fn lookup(&self, path: &str) -> Lookup<'_> {
+        let mut current = self;
+        for segment in path.split('.').filter(|segment| !segment.is_empty()) {
+            current = match &current.kind {
+                JsonKind::Redacted => return Lookup::Redacted(current),
+                JsonKind::Object(members) => {
+                    match members
+                        .iter()
+                        .find(|member| member.key.as_deref() == Some(segment))
+                    {
+                        Some(member) => &member.value,
+                        None => {
+                            return match hiding_member(members) {
+                                Some(hidden) => Lookup::Redacted(&hidden.value),
+                                None => Lookup::Missing,
+                            }
+                        }
+                    }
+                }
+                JsonKind::Array(elems) => {
+                    let Ok(idx) = segment.parse::<usize>() else {
+                        return Lookup::Missing;
+                    };
+                    // Redacted elements may stand for any number of elements, so indices
+                    // past one are unknown.
+                    match elems.iter().position(|elem| elem.is_redacted()) {
+                        Some(redacted) if redacted <= idx => {
+                            return Lookup::Redacted(&elems[redacted])
+                        }
+                        _ => match elems.get(idx) {
+                            Some(elem) => elem,
+                            None => return Lookup::Missing,
+                        },
+                    }
+                }
+                _ => return Lookup::Missing,
+            };
+        }
+
+        if current.is_redacted() {
+            Lookup::Redacted(current)
+        } else {
+            Lookup::Found(current)
+        }
+    }
+
+    /// Whether this is an object whose redacted parts could hide members besides those parsed.
+    pub fn may_hide_members(&self) -> bool {
+        match &self.kind {
+            JsonKind::Object(members) => hiding_member(members).is_some(),
+            _ => false,
+        }
+    }
+
+    pub fn is_redacted(&self) -> bool {
+        matches!(self.kind, JsonKind::Redacted)
+    }
+
+    /// Whether this value contains no redacted parts.
+    pub fn is_fully_revealed(&self) -> bool {
+        match &self.kind {
+            JsonKind::Redacted => false,
+            JsonKind::Array(elems) => elems.iter().all(Json::is_fully_revealed),
+            JsonKind::Object(members) => members
+                .iter()
+                .all(|member| member.key.is_some() && member.value.is_fully_revealed()),
+            _ => true,
+        }
+    }
+
+    /// Converts the value, or returns `None` if any part of it is redacted.
+    pub fn to_value(&self) -> Option<serde_json::Value> {
+        use serde_json::Value;
+        Some(match &self.kind {
+            JsonKind::Null => Value::Null,
+            JsonKind::Bool(value) => Value::Bool(*value),
+            JsonKind::Number(literal) => Value::Number(serde_json::from_str(literal).ok()?),
+            JsonKind::String(value) => Value::String(value.clone()),
+            JsonKind::Array(elems) => {
+                Value::Array(elems.iter().map(Json::to_value).collect::<Option<_>>()?)
+            }
+            JsonKind::Object(members) => Value::Object(
+                members
+                    .iter()
+                    .map(|member| Some((member.key.clone()?, member.value.to_value()?)))
+                    .collect::<Option<_>>()?,
+            ),
+            JsonKind::Redacted => return None,
+        })
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match &self.kind {
+            JsonKind::String(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+/// The first member that could hide others: one with a redacted key, or a redacted value, whose
+/// hidden bytes may run past its end into sibling members, e.g. `"x", "b": 1`.
+fn hiding_member(members: &[Member]) -> Option<&Member> {
+    members
+        .iter()
+        .find(|member| member.key.is_none() || member.value.is_redacted())
+}
+
+struct Parser<'a> {
+    input: &'a [u8],
+    revealed: &'a [bool],
+    pos: usize,
+    /// Number of arrays and objects around the current position.
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn value(&mut self) -> Result<Json, eyre::ErrReport> {
+        let start = self.pos;
+        if self.is_redacted() {
+            self.skip_redacted();
+            return Ok(Json {
+                kind: JsonKind::Redacted,
+                span: start..self.pos,
+            });
+        }
+
+        let kind = match self.peek() {
+            Some(b'{') => self.nested(Self::object)?,
+            Some(b'[') => self.nested(Self::array)?,
+            Some(b'"') => match self.string()? {
+                Some(value) => JsonKind::String(value),
+                None => JsonKind::Redacted,
+            },
+            Some(b't') => self.literal("true", JsonKind::Bool(true))?,
+            Some(b'f') => self.literal("false", JsonKind::Bool(false))?,
+            Some(b'n') => self.literal("null", JsonKind::Null)?,
+            Some(b'-' | b'0'..=b'9') => self.number()?,
+            Some(byte) => {
+                return Err(eyre!(
+                    "Unexpected character {:?} in JSON at {}",
+                    byte as char,
+                    self.pos
+                ))
+            }
+            None => return Err(eyre!("Unexpected end of JSON")),
+        };
+
+        Ok(Json {
+            kind,
+            span: start..self.pos,
+        })
+    }
+
+    /// Parses an array or object with `parse`, unless it would exceed [`MAX_DEPTH`].
+    fn nested(
+        &mut self,
+        parse: fn(&mut Self) -> Result<JsonKind, eyre::ErrReport>,
+    ) -> Result<JsonKind, eyre::ErrReport> {
+        if self.depth == MAX_DEPTH {
+            return Err(eyre!(
+                "JSON is nested deeper than {MAX_DEPTH} levels at {}",
+                self.pos
+            ));
+        }
+        self.depth += 1;
+        let kind = parse(self)?;
+        self.depth -= 1;
+        Ok(kind)
+    }
+
+    fn object(&mut self) -> Result<JsonKind, eyre::ErrReport> {
+        self.pos += 1;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.eat(b'}') {
+            return Ok(JsonKind::Object(members));
+        }
+
+        loop {
+            self.skip_whitespace();
+            let start = self.pos;
+            if self.is_redacted() {
+                // A redacted run in key position hides one or more whole members.
+                self.skip_redacted();
+                members.push(Member {
+                    key: None,
+                    value: Json {
+                        kind: JsonKind::Redacted,
+                        span: start..self.pos,
+                    },
+                });
+            } else {
+                if self.peek() != Some(b'"') {
+                    return Err(eyre!("Expected object key in JSON at {}", self.pos));
+                }
+                let key = self.string()?;
+                self.skip_whitespace();
+                if !self.eat(b':') {
+                    return Err(eyre!("Expected ':' in JSON at {}", self.pos));
+                }
+                self.skip_whitespace();
+                let value = self.value()?;
+                members.push(Member { key, value });
+            }
+
+            self.skip_whitespace();
+            if self.eat(b',') {
+                continue;
+            }
+            if self.eat(b'}') {
+                return Ok(JsonKind::Object(members));
+            }
+            return Err(eyre!(
+                "Expected ',' or '}}' in JSON at {}, the structure may be redacted",
+                self.pos
+            ));
+        }
+    }
+
+    fn array(&mut self) -> Result<JsonKind, eyre::ErrReport> {
+        self.pos += 1;
+        let mut elems = Vec::new();
+        self.skip_whitespace();
+        if self.eat(b']') {
+            return Ok(JsonKind::Array(elems));
+        }
+
+        loop {
+            self.skip_whitespace();
+            elems.push(self.value()?);
+            self.skip_whitespace();
+            if self.eat(b',') {
+                continue;
+            }
+            if self.eat(b']') {
+                return Ok(JsonKind::Array(elems));
+            }
+            return Err(eyre!(
+                "Expected ',' or ']' in JSON at {}, the structure may be redacted",
+                self.pos
+            ));
+        }
+    }
+
+    /// Parses a string, returning `None` if any of its content is redacted.
+    fn string(&mut self) -> Result<Option<String>, eyre::ErrReport> {
+        let start = self.pos;
+        self.pos += 1;
+        let mut redacted = false;
+        let mut escaped = false;
+        loop {
+            let Some(&byte) = self.input.get(self.pos) else {
+                return Err(eyre!("Unterminated string in JSON at {start}"));
+            };
+            if !self.revealed[self.pos] {
+                redacted = true;
+                escaped = false;
+            } else if escaped {
+                escaped = false;
+            } else if byte == b'\\' {
+                escaped = true;
+            } else if byte == b'"' {
+                self.pos += 1;
+                break;
+            }
+            self.pos += 1;
+        }
+
+        if redacted {
+            return Ok(None);
+        }
+        let value: String = serde_json::from_slice(&self.input[start..self.pos])
+            .map_err(|err| eyre!("Invalid string in JSON at {start}: {err}"))?;
+        Ok(Some(value))
+    }
+
+    fn number(&mut self) -> Result<JsonKind, eyre::ErrReport> {
+        let start = self.pos;
+        while self.peek().is_some_and(|byte| {
+            byte.is_ascii_digit() || matches!(byte, b'-' | b'+' | b'.' | b'e' | b'E')
+        }) {
+            self.pos += 1;
+        }
+        // A number directly followed by redacted bytes may have more digits.
+        if self.is_redacted() {
+            self.skip_redacted();
+            return Ok(JsonKind::Redacted);
+        }
+
+        let literal = std::str::from_utf8(&self.input[start..self.pos]).expect("ascii digits");
+        serde_json::from_str::<serde_json::Number>(literal)
+            .map_err(|err| eyre!("Invalid number in JSON at {start}: {err}"))?;
+        Ok(JsonKind::Number(literal.to_string()))
+    }
+
+    fn literal(&mut self, literal: &str, kind: JsonKind) -> Result<JsonKind, eyre::ErrReport> {
+        let end = self.pos + literal.len();
+        if self.input.get(self.pos..end) != Some(literal.as_bytes())
+            || self.revealed[self.pos..end].contains(&false)
+        {
+            return Err(eyre!("Invalid literal in JSON at {}", self.pos));
+        }
+        self.pos = end;
+        Ok(kind)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.pos).copied()
+    }
+
+    fn is_redacted(&self) -> bool {
+        self.pos < self.input.len() && !self.revealed[self.pos]
+    }
+
+    fn skip_redacted(&mut self) {
+        while self.is_redacted() {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        if self.peek() == Some(byte) && !self.is_redacted() {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while !self.is_redacted() && self.peek().is_some_and(|byte| byte.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses `input`, treating `*` as a redacted byte.
+    fn parse(input: &str) -> Json {
+        let revealed = input.bytes().map(|byte| byte != b'*').collect::<Vec<_>>();
+        Json::parse(input.as_bytes(), &revealed).unwrap()
+    }
+
+    #[test]
+    fn limits_nesting_depth() {
+        let nested = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        let revealed = vec![true; 200_000];
+
+        assert!(Json::parse(nested(MAX_DEPTH).as_bytes(), &revealed).is_ok());
+        let err = Json::parse(nested(MAX_DEPTH + 1).as_bytes(), &revealed).unwrap_err();
+        assert!(err.to_string().contains("nested deeper"), "{err}");
+        // Would overflow the stack without the limit.
+        assert!(Json::parse("[".repeat(100_000).as_bytes(), &revealed).is_err());
+    }
+
+    #[test]
+    fn parses_fully_revealed_json() {
+        let json = parse(r#"{"a": {"b": [1, -2.5e3, "x\"y"]}, "c": true, "d": null}"#);
+
+        assert!(json.is_fully_revealed());
+        let Lookup::Found(value) = json.lookup("a.b.2") else {
+            panic!("value should be found");
+        };
+        assert_eq!(value.as_str(), Some("x\"y"));
+        assert_eq!(
+            json.lookup("a.b.1"),
+            Lookup::Found(&Json {
+                kind: JsonKind::Number("-2.5e3".to_string()),
+                span: 16..22,
+            })
+        );
+        assert_eq!(json.lookup("a.x"), Lookup::Missing);
+        assert_eq!(
+            json.to_value().unwrap(),
+            serde_json::json!({"a": {"b": [1, -2500.0, "x\"y"]}, "c": true, "d": null})
+        );
+    }
+
+    #[test]
+    fn distinguishes_redacted_from_missing() {
+        let json = parse(r#"{"name": "*****", "age": ***, ********, "tags": ["a", ***, "c"]}"#);
+
+        assert!(!json.is_fully_revealed());
+        assert!(matches!(json.lookup("name"), Lookup::Redacted(_)));
+        assert!(matches!(json.lookup("age"), Lookup::Redacted(_)));
+        // Could be hidden in the redacted member.
+        assert!(matches!(json.lookup("email"), Lookup::Redacted(_)));
+        assert!(matches!(json.lookup("tags.0"), Lookup::Found(_)));
+        assert!(matches!(json.lookup("tags.2"), Lookup::Redacted(_)));
+    }
+
+    #[test]
+    fn redacted_values_may_hide_members() {
+        // The hidden bytes may be `"x", "b": 1`.
+        let json = parse(r#"{"a": ***********}"#);
+        assert!(json.may_hide_members());
+        assert!(matches!(json.lookup("b"), Lookup::Redacted(_)));
+
+        // Even a string whose quotes are revealed, e.g. `"x", "b": "y"`.
+        let json = parse(r#"{"a": "***********", "c": 1}"#);
+        assert!(matches!(json.lookup("b"), Lookup::Redacted(_)));
+        assert!(matches!(json.lookup("c"), Lookup::Found(_)));
+
+        let json = parse(r#"{"a": "x", "c": 1}"#);
+        assert!(!json.may_hide_members());
+        assert_eq!(json.lookup("b"), Lookup::Missing);
+    }
+
+    #[test]
+    fn rejects_redacted_structure() {
+        let input = r#"****"name": "Alice"****"#;
+        let revealed = input.bytes().map(|byte| byte != b'*').collect::<Vec<_>>();
+
+        assert!(Json::parse(input.as_bytes(), &revealed).is_err());
+    }
+}
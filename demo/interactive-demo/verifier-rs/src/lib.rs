@@ -1,10 +1,4 @@
-use axum::{
-    extract::{Request, State},
-    response::IntoResponse,
-    routing::get,
-    Router,
-};
-use axum_websocket::{WebSocket, WebSocketUpgrade};
+use axum::extract::Request;
 use eyre::eyre;
 use hyper::{body::Incoming, server::conn::http1};
 use hyper_util::rt::TokioIo;
@@ -23,25 +17,32 @@ use tokio::{
 use tokio_util::compat::TokioAsyncReadCompatExt;
 use tower_service::Service;
 use tracing::{debug, error, info};
-use ws_stream_tungstenite::WsStream;
 
 mod axum_websocket;
-
-// Maximum number of bytes that can be sent from prover to server
-const MAX_SENT_DATA: usize = 1 << 12;
-// Maximum number of bytes that can be received by prover from server
-const MAX_RECV_DATA: usize = 1 << 14;
-
-/// Global data that needs to be shared with the axum handlers
-#[derive(Clone, Debug)]
-struct VerifierGlobals {
-    pub server_domain: String,
-}
+pub mod http;
+pub mod json;
+pub mod policy;
+pub mod predicate;
+pub mod presentation;
+pub mod render;
+pub mod replay;
+pub mod script;
+mod service;
+mod tcp;
+pub mod transcript;
+
+use policy::TranscriptPolicy;
+use render::Rendering;
+pub use service::{
+    Limits, Metrics, PolicySource, Transport, VerificationOutcome, VerifiedData, VerifierService,
+    VerifierServiceBuilder, NONCE_HEADER, SUPPORTED_PROTOCOLS,
+};
+use transcript::Revealed;
 
 pub async fn run_server(
     verifier_host: &str,
     verifier_port: u16,
-    server_domain: &str,
+    service: VerifierService,
 ) -> Result<(), eyre::ErrReport> {
     let verifier_address = SocketAddr::new(
         IpAddr::V4(verifier_host.parse().map_err(|err| {
@@ -56,11 +57,7 @@ pub async fn run_server(
     info!("Listening for TCP traffic at {}", verifier_address);
 
     let protocol = Arc::new(http1::Builder::new());
-    let router = Router::new()
-        .route("/verify", get(ws_handler))
-        .with_state(VerifierGlobals {
-            server_domain: server_domain.to_string(),
-        });
+    let router = service.into_router::<()>();
 
     loop {
         let stream = match listener.accept().await {
@@ -93,40 +90,18 @@ pub async fn run_server(
     }
 }
 
-async fn ws_handler(
-    ws: WebSocketUpgrade,
-    State(verifier_globals): State<VerifierGlobals>,
-) -> impl IntoResponse {
-    info!("Received websocket request");
-    ws.on_upgrade(|socket| handle_socket(socket, verifier_globals))
-}
-
-async fn handle_socket(socket: WebSocket, verifier_globals: VerifierGlobals) {
-    debug!("Upgraded to websocket connection");
-    let stream = WsStream::new(socket.into_inner());
-
-    match verifier(stream, &verifier_globals.server_domain).await {
-        Ok((sent, received)) => {
-            info!("Successfully verified {}", &verifier_globals.server_domain);
-            info!("Verified sent data:\n{}", sent,);
-            println!("Verified received data:\n{received}",);
-        }
-        Err(err) => {
-            error!("Failed verification using websocket: {err}");
-        }
-    }
-}
-
-async fn verifier<T: AsyncWrite + AsyncRead + Send + Unpin + 'static>(
+pub(crate) async fn verifier<T: AsyncWrite + AsyncRead + Send + Unpin + 'static>(
     socket: T,
-    server_domain: &str,
-) -> Result<(String, String), eyre::ErrReport> {
+    policy: &dyn TranscriptPolicy,
+    limits: &Limits,
+    rendering: &Rendering,
+) -> Result<VerifiedData, eyre::ErrReport> {
     debug!("Starting verification...");
 
     // Setup Verifier.
     let config_validator = ProtocolConfigValidator::builder()
-        .max_sent_data(MAX_SENT_DATA)
-        .max_recv_data(MAX_RECV_DATA)
+        .max_sent_data(limits.max_sent_data)
+        .max_recv_data(limits.max_recv_data)
         .build()
         .unwrap();
 
@@ -140,54 +115,41 @@ async fn verifier<T: AsyncWrite + AsyncRead + Send + Unpin + 'static>(
     debug!("Starting MPC-TLS verification...");
 
     let verify_config = VerifyConfig::default();
-    let VerifierOutput {
-        server_name,
-        transcript,
-        ..
-    } = verifier
+    let output = verifier
         .verify(socket.compat(), &verify_config)
         .await
-        .unwrap();
-
-    let transcript = transcript.expect("prover should have revealed transcript data");
-
-    // Check sent data: check host.
-    debug!("Starting sent data verification...");
-    let sent = transcript.sent_unsafe().to_vec();
-    let sent_data = String::from_utf8(sent.clone()).expect("Verifier expected sent data");
-    sent_data
-        .find(server_domain)
-        .ok_or_else(|| eyre!("Verification failed: Expected host {}", server_domain))?;
-
-    // Check received data: check json and version number.
-    debug!("Starting received data verification...");
-    let received = transcript.received_unsafe().to_vec();
-    let response = String::from_utf8(received.clone()).expect("Verifier expected received data");
-
-    debug!("Received data: {:?}", response);
-    response
-        .find("123 Elm Street")
-        .ok_or_else(|| eyre!("Verification failed: missing data in received data"))?;
-
-    // Check Session info: server name.
-    if let Some(server_name) = server_name {
-        if server_name.as_str() != server_domain {
-            return Err(eyre!("Verification failed: server name mismatches"));
-        }
+        .map_err(|err| eyre!("MPC-TLS verification failed: {err}"))?;
+
+    // Check revealed data against the policy.
+    debug!("Starting policy verification...");
+    let verdict = policy.check(&output).await;
+    debug!("Policy verdict:\n{verdict}");
+    if verdict.passed {
+        policy.commit(&output);
     } else {
-        // TODO: https://github.com/tlsnotary/tlsn-js/issues/110
-        // return Err(eyre!("Verification failed: server name is missing"));
+        policy.rollback(&output);
     }
 
-    let sent_string = bytes_to_redacted_string(&sent)?;
-    let received_string = bytes_to_redacted_string(&received)?;
-
-    Ok((sent_string, received_string))
-}
-
-/// Render redacted bytes as `🙈`.
-fn bytes_to_redacted_string(bytes: &[u8]) -> Result<String, eyre::ErrReport> {
-    Ok(String::from_utf8(bytes.to_vec())
-        .map_err(|err| eyre!("Failed to parse bytes to redacted string: {err}"))?
-        .replace('\0', "🙈"))
+    let connection_time = policy::connection_time(&output);
+    let VerifierOutput {
+        server_name,
+        transcript,
+        ..
+    } = output;
+    let transcript =
+        transcript.ok_or_else(|| eyre!("Prover should have revealed transcript data"))?;
+
+    let sent_segments = render::segments(&Revealed::sent(&transcript));
+    let received_segments = render::segments(&Revealed::received(&transcript));
+
+    Ok(VerifiedData {
+        server_name: server_name.map(|name| name.as_str().to_string()),
+        connection_time,
+        duplicate: verdict.is_duplicate(),
+        verdict,
+        sent: rendering.render(&sent_segments),
+        received: rendering.render(&received_segments),
+        sent_segments,
+        received_segments,
+    })
 }
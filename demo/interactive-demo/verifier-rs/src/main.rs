@@ -1,4 +1,12 @@
-use interactive_networked_verifier::run_server;
+use interactive_networked_verifier::{
+    policy::{
+        all, Freshness, NonceLocation, ReceivedContains, SentContains, ServerName, TranscriptPolicy,
+    },
+    run_server,
+    script::{ScriptFile, ScriptLimits},
+    VerifierService, NONCE_HEADER,
+};
+use std::time::Duration;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
 
 const TRACING_FILTER: &str = "INFO";
@@ -9,6 +17,15 @@ const VERIFIER_PORT: u16 = 9816;
 /// Make sure the following domain is the same in SERVER_URL on the prover side
 const SERVER_DOMAIN: &str = "raw.githubusercontent.com";
 
+/// Maximum age of the prover's connection to the server
+const MAX_AGE: Duration = Duration::from_secs(5 * 60);
+/// Allowed difference between the verifier's clock and the connection or `Date` header time
+const MAX_CLOCK_SKEW: Duration = Duration::from_secs(30);
+
+/// Optional Rhai script with the verification rules, e.g. `policies/example.rhai`, used instead
+/// of the built-in checks below. It is re-read for every session.
+const POLICY_SCRIPT: Option<&str> = None;
+
 #[tokio::main]
 async fn main() -> Result<(), eyre::ErrReport> {
     tracing_subscriber::registry()
@@ -16,7 +33,27 @@ async fn main() -> Result<(), eyre::ErrReport> {
         .with(tracing_subscriber::fmt::layer())
         .init();
 
-    run_server(VERIFIER_HOST, VERIFIER_PORT, SERVER_DOMAIN).await?;
+    let policy = all([
+        ServerName::new(SERVER_DOMAIN).allow_missing().boxed(),
+        Freshness::new(MAX_AGE)
+            .max_skew(MAX_CLOCK_SKEW)
+            .date_header()
+            .boxed(),
+        SentContains::new(SERVER_DOMAIN).boxed(),
+        ReceivedContains::new("123 Elm Street").boxed(),
+    ]);
+    let service = match POLICY_SCRIPT {
+        Some(path) => VerifierService::builder(ScriptFile {
+            path: path.into(),
+            limits: ScriptLimits::default(),
+        }),
+        None => VerifierService::builder(policy.boxed()),
+    }
+    // The prover sends the nonce back in a header of the same name.
+    .nonce(NonceLocation::Header(NONCE_HEADER.into()))
+    .build();
+
+    run_server(VERIFIER_HOST, VERIFIER_PORT, service).await?;
 
     Ok(())
 }
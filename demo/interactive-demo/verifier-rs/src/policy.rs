@@ -0,0 +1,1148 @@
+//! Policies applied to the data revealed by the prover.
+//!
+//! A [`TranscriptPolicy`] receives the [`VerifierOutput`] of an MPC-TLS session and returns a
+//! [`Verdict`]. Policies compose with [`all`], [`any`] and [`not`], so custom checks, e.g.
+//! cross-referencing revealed ids with a database, can be mixed with the built-in ones.
+use async_trait::async_trait;
+use eyre::eyre;
+use regex::bytes::Regex;
+use serde::Serialize;
+use std::{
+    fmt,
+    ops::Range,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tlsn_core::VerifierOutput;
+
+use crate::{
+    http::{self, Body, Exchange},
+    json::{Json, JsonKind, Lookup},
+    predicate::{self, Decimal, Predicate},
+    transcript::Revealed,
+};
+
+/// Verdict of a [`TranscriptPolicy`], including the verdicts of nested policies.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Verdict {
+    /// Name of the policy.
+    pub policy: String,
+    pub passed: bool,
+    /// Why the policy failed, or a note on how it passed.
+    pub reason: Option<String>,
+    /// Whether the policy found the session to duplicate an earlier one.
+    pub duplicate: bool,
+    pub children: Vec<Verdict>,
+}
+
+impl Verdict {
+    pub fn pass(policy: impl Into<String>) -> Self {
+        Self {
+            policy: policy.into(),
+            passed: true,
+            reason: None,
+            duplicate: false,
+            children: Vec::new(),
+        }
+    }
+
+    pub fn fail(policy: impl Into<String>, reason: impl Into<String>) -> Self {
+        Self {
+            policy: policy.into(),
+            passed: false,
+            reason: Some(reason.into()),
+            duplicate: false,
+            children: Vec::new(),
+        }
+    }
+
+    /// Attaches a reason to this verdict.
+    pub fn with_reason(mut self, reason: impl Into<String>) -> Self {
+        self.reason = Some(reason.into());
+        self
+    }
+
+    /// Marks the session as a duplicate of an earlier one.
+    pub fn flag_duplicate(mut self) -> Self {
+        self.duplicate = true;
+        self
+    }
+
+    /// Whether this verdict or any nested one flags the session as a duplicate.
+    pub fn is_duplicate(&self) -> bool {
+        self.duplicate || self.children.iter().any(Verdict::is_duplicate)
+    }
+
+    fn fmt_indented(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        let mark = if self.passed { "pass" } else { "FAIL" };
+        write!(
+            f,
+            "{:indent$}[{mark}] {}",
+            "",
+            self.policy,
+            indent = depth * 2
+        )?;
+        if let Some(reason) = &self.reason {
+            write!(f, ": {reason}")?;
+        }
+        if self.duplicate {
+            write!(f, " (duplicate)")?;
+        }
+        for child in &self.children {
+            writeln!(f)?;
+            child.fmt_indented(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_indented(f, 0)
+    }
+}
+
+/// A check on the data revealed in an MPC-TLS session.
+#[async_trait]
+pub trait TranscriptPolicy: Send + Sync + 'static {
+    async fn check(&self, output: &VerifierOutput) -> Verdict;
+
+    /// Called once the overall verdict on the session passed, for policies keeping state about
+    /// accepted sessions. Does nothing by default.
+    fn commit(&self, _output: &VerifierOutput) {}
+
+    /// Called instead of [`commit`](Self::commit) once the overall verdict on the session failed,
+    /// to undo what `check` reserved for it. Does nothing by default.
+    fn rollback(&self, _output: &VerifierOutput) {}
+
+    /// Type-erases this policy so it can be combined with others.
+    fn boxed(self) -> Arc<dyn TranscriptPolicy>
+    where
+        Self: Sized,
+    {
+        Arc::new(self)
+    }
+}
+
+#[async_trait]
+impl TranscriptPolicy for Arc<dyn TranscriptPolicy> {
+    async fn check(&self, output: &VerifierOutput) -> Verdict {
+        self.as_ref().check(output).await
+    }
+
+    fn commit(&self, output: &VerifierOutput) {
+        self.as_ref().commit(output)
+    }
+
+    fn rollback(&self, output: &VerifierOutput) {
+        self.as_ref().rollback(output)
+    }
+
+    fn boxed(self) -> Arc<dyn TranscriptPolicy> {
+        self
+    }
+}
+
+/// Passes if all policies pass. Every policy is checked so all failures are reported.
+pub struct All(Vec<Arc<dyn TranscriptPolicy>>);
+
+/// Passes if any policy passes.
+pub struct Any(Vec<Arc<dyn TranscriptPolicy>>);
+
+/// Passes if the policy fails.
+pub struct Not(Arc<dyn TranscriptPolicy>);
+
+pub fn all(policies: impl IntoIterator<Item = Arc<dyn TranscriptPolicy>>) -> All {
+    All(policies.into_iter().collect())
+}
+
+pub fn any(policies: impl IntoIterator<Item = Arc<dyn TranscriptPolicy>>) -> Any {
+    Any(policies.into_iter().collect())
+}
+
+pub fn not(policy: impl TranscriptPolicy) -> Not {
+    Not(policy.boxed())
+}
+
+#[async_trait]
+impl TranscriptPolicy for All {
+    async fn check(&self, output: &VerifierOutput) -> Verdict {
+        let mut children = Vec::with_capacity(self.0.len());
+        for policy in &self.0 {
+            children.push(policy.check(output).await);
+        }
+        Verdict {
+            policy: "all".to_string(),
+            passed: children.iter().all(|verdict| verdict.passed),
+            reason: None,
+            duplicate: false,
+            children,
+        }
+    }
+
+    fn commit(&self, output: &VerifierOutput) {
+        self.0.iter().for_each(|policy| policy.commit(output));
+    }
+
+    fn rollback(&self, output: &VerifierOutput) {
+        self.0.iter().for_each(|policy| policy.rollback(output));
+    }
+}
+
+#[async_trait]
+impl TranscriptPolicy for Any {
+    async fn check(&self, output: &VerifierOutput) -> Verdict {
+        let mut children = Vec::with_capacity(self.0.len());
+        for policy in &self.0 {
+            children.push(policy.check(output).await);
+        }
+        Verdict {
+            policy: "any".to_string(),
+            passed: children.iter().any(|verdict| verdict.passed),
+            reason: None,
+            duplicate: false,
+            children,
+        }
+    }
+
+    fn commit(&self, output: &VerifierOutput) {
+        self.0.iter().for_each(|policy| policy.commit(output));
+    }
+
+    fn rollback(&self, output: &VerifierOutput) {
+        self.0.iter().for_each(|policy| policy.rollback(output));
+    }
+}
+
+#[async_trait]
+impl TranscriptPolicy for Not {
+    async fn check(&self, output: &VerifierOutput) -> Verdict {
+        let verdict = self.0.check(output).await;
+        Verdict {
+            policy: "not".to_string(),
+            passed: !verdict.passed,
+            reason: None,
+            duplicate: false,
+            children: vec![verdict],
+        }
+    }
+
+    fn commit(&self, output: &VerifierOutput) {
+        self.0.commit(output)
+    }
+
+    fn rollback(&self, output: &VerifierOutput) {
+        self.0.rollback(output)
+    }
+}
+
+/// Checks the server name of the TLS session.
+pub struct ServerName {
+    expected: String,
+    allow_missing: bool,
+}
+
+impl ServerName {
+    pub fn new(expected: impl Into<String>) -> Self {
+        Self {
+            expected: expected.into(),
+            allow_missing: false,
+        }
+    }
+
+    /// Passes when the prover did not reveal the server name.
+    // TODO: https://github.com/tlsnotary/tlsn-js/issues/110
+    pub fn allow_missing(mut self) -> Self {
+        self.allow_missing = true;
+        self
+    }
+}
+
+#[async_trait]
+impl TranscriptPolicy for ServerName {
+    async fn check(&self, output: &VerifierOutput) -> Verdict {
+        let policy = format!("server name is {}", self.expected);
+        match &output.server_name {
+            Some(server_name) if server_name.as_str() == self.expected => Verdict::pass(policy),
+            Some(server_name) => {
+                Verdict::fail(policy, format!("server name is {}", server_name.as_str()))
+            }
+            None if self.allow_missing => {
+                Verdict::pass(policy).with_reason("server name was not revealed")
+            }
+            None => Verdict::fail(policy, "server name was not revealed"),
+        }
+    }
+}
+
+/// A byte string to search for in the revealed data, where `None` matches any revealed byte.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BytePattern(Vec<Option<u8>>);
+
+impl BytePattern {
+    /// Matches `bytes` exactly.
+    pub fn literal(bytes: impl AsRef<[u8]>) -> Self {
+        Self(bytes.as_ref().iter().copied().map(Some).collect())
+    }
+
+    /// Parses hex digits, where `??` matches any byte, e.g. `"1f 8b ?? 08"`. Whitespace is
+    /// ignored.
+    pub fn hex(pattern: &str) -> Result<Self, eyre::ErrReport> {
+        let digits = pattern
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<Vec<_>>();
+        if digits.len() % 2 != 0 {
+            return Err(eyre!("Hex pattern {pattern:?} has an odd number of digits"));
+        }
+        digits
+            .chunks(2)
+            .map(|pair| match pair {
+                ['?', '?'] => Ok(None),
+                [high, low] => match (high.to_digit(16), low.to_digit(16)) {
+                    (Some(high), Some(low)) => Ok(Some((high * 16 + low) as u8)),
+                    _ => Err(eyre!("Invalid byte {high}{low} in hex pattern {pattern:?}")),
+                },
+                _ => unreachable!("chunks of an even-length slice are pairs"),
+            })
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+
+    /// Finds the first occurrence whose bytes are all revealed.
+    pub fn find(&self, revealed: &Revealed<'_>) -> Option<usize> {
+        let len = self.0.len();
+        if len > revealed.len() {
+            return None;
+        }
+        (0..=revealed.len() - len).find(|&idx| {
+            revealed.is_range_revealed(idx..idx + len)
+                && self
+                    .0
+                    .iter()
+                    .zip(&revealed.data()[idx..idx + len])
+                    .all(|(expected, byte)| expected.is_none_or(|expected| expected == *byte))
+        })
+    }
+}
+
+impl fmt::Display for BytePattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let printable = self
+            .0
+            .iter()
+            .all(|byte| byte.is_some_and(|byte| byte.is_ascii_graphic() || byte == b' '));
+        if printable {
+            let text = self.0.iter().flatten().map(|&byte| byte as char);
+            write!(f, "{:?}", text.collect::<String>())
+        } else {
+            for byte in &self.0 {
+                match byte {
+                    Some(byte) => write!(f, "{byte:02x}")?,
+                    None => write!(f, "??")?,
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Checks that the revealed sent data contains a byte string, e.g. the expected host.
+pub struct SentContains(BytePattern);
+
+impl SentContains {
+    pub fn new(needle: impl AsRef<[u8]>) -> Self {
+        Self(BytePattern::literal(needle))
+    }
+
+    pub fn pattern(pattern: BytePattern) -> Self {
+        Self(pattern)
+    }
+}
+
+#[async_trait]
+impl TranscriptPolicy for SentContains {
+    async fn check(&self, output: &VerifierOutput) -> Verdict {
+        let policy = format!("sent data contains {}", self.0);
+        match &output.transcript {
+            Some(transcript) if self.0.find(&Revealed::sent(transcript)).is_some() => {
+                Verdict::pass(policy)
+            }
+            Some(_) => Verdict::fail(policy, "missing data in sent data"),
+            None => Verdict::fail(policy, "no transcript data was revealed"),
+        }
+    }
+}
+
+/// Checks that the revealed received data contains a byte string.
+pub struct ReceivedContains(BytePattern);
+
+impl ReceivedContains {
+    pub fn new(needle: impl AsRef<[u8]>) -> Self {
+        Self(BytePattern::literal(needle))
+    }
+
+    pub fn pattern(pattern: BytePattern) -> Self {
+        Self(pattern)
+    }
+}
+
+#[async_trait]
+impl TranscriptPolicy for ReceivedContains {
+    async fn check(&self, output: &VerifierOutput) -> Verdict {
+        let policy = format!("received data contains {}", self.0);
+        match &output.transcript {
+            Some(transcript) if self.0.find(&Revealed::received(transcript)).is_some() => {
+                Verdict::pass(policy)
+            }
+            Some(_) => Verdict::fail(policy, "missing data in received data"),
+            None => Verdict::fail(policy, "no transcript data was revealed"),
+        }
+    }
+}
+
+/// Checks the request and response of one exchange of the session, e.g. that the second
+/// exchange is `GET /api/me`. Exchanges are counted from 0.
+pub struct NthExchange {
+    index: usize,
+    method: Option<String>,
+    target: Option<String>,
+    status: Option<u16>,
+}
+
+impl NthExchange {
+    /// Requires the session to have an exchange at `index`.
+    pub fn new(index: usize) -> Self {
+        Self {
+            index,
+            method: None,
+            target: None,
+            status: None,
+        }
+    }
+
+    /// Requires the request to have `method` and `target`.
+    pub fn request(mut self, method: impl Into<String>, target: impl Into<String>) -> Self {
+        self.method = Some(method.into());
+        self.target = Some(target.into());
+        self
+    }
+
+    /// Requires the response to have `status`.
+    pub fn status(mut self, status: u16) -> Self {
+        self.status = Some(status);
+        self
+    }
+
+    fn name(&self) -> String {
+        let mut policy = format!("exchange {}", self.index);
+        if let (Some(method), Some(target)) = (&self.method, &self.target) {
+            policy.push_str(&format!(" is {method} {target}"));
+        }
+        if let Some(status) = self.status {
+            policy.push_str(&format!(" with status {status}"));
+        }
+        policy
+    }
+
+    /// Checks the exchanges parsed from a session.
+    pub(crate) fn check_exchanges(&self, exchanges: &[Exchange]) -> Verdict {
+        let policy = self.name();
+        let Some(exchange) = exchanges.get(self.index) else {
+            return Verdict::fail(policy, format!("session has {} exchanges", exchanges.len()));
+        };
+
+        let expectations = [
+            (
+                "method",
+                self.method.clone(),
+                exchange.request.method.clone(),
+            ),
+            (
+                "target",
+                self.target.clone(),
+                exchange.request.target.clone(),
+            ),
+            (
+                "status",
+                self.status.map(|status| status.to_string()),
+                exchange.response.status.map(|status| status.to_string()),
+            ),
+        ];
+        for (field, expected, actual) in expectations {
+            let Some(expected) = expected else {
+                continue;
+            };
+            match actual {
+                Some(actual) if actual == expected => {}
+                Some(actual) => return Verdict::fail(policy, format!("{field} is {actual}")),
+                None => return Verdict::fail(policy, format!("{field} is redacted")),
+            }
+        }
+        Verdict::pass(policy)
+    }
+}
+
+#[async_trait]
+impl TranscriptPolicy for NthExchange {
+    async fn check(&self, output: &VerifierOutput) -> Verdict {
+        match exchanges(output) {
+            Ok(exchanges) => self.check_exchanges(&exchanges),
+            Err(err) => Verdict::fail(self.name(), err.to_string()),
+        }
+    }
+}
+
+/// Checks a value in the JSON body of a response, looked up by a dot-separated path such as
+/// `accounts.0.balance`. Chunked bodies are de-chunked first, and verdicts report where the value
+/// is in the received data.
+pub struct ResponseJson {
+    path: String,
+    predicate: Option<Predicate>,
+    exchange: usize,
+}
+
+impl ResponseJson {
+    /// Requires the value at `path` in the first response to be revealed.
+    pub fn new(path: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            predicate: None,
+            exchange: 0,
+        }
+    }
+
+    /// Checks the response of the exchange at `index` instead of the first one.
+    pub fn exchange(mut self, index: usize) -> Self {
+        self.exchange = index;
+        self
+    }
+
+    /// Requires the value to equal `expected`.
+    pub fn equals(self, expected: impl Into<serde_json::Value>) -> Self {
+        self.satisfies(Predicate::equals(expected))
+    }
+
+    /// Requires the value to satisfy `predicate`, e.g. `Predicate::at_least("1000")?`.
+    pub fn satisfies(mut self, predicate: Predicate) -> Self {
+        self.predicate = Some(predicate);
+        self
+    }
+}
+
+#[async_trait]
+impl TranscriptPolicy for ResponseJson {
+    async fn check(&self, output: &VerifierOutput) -> Verdict {
+        let policy = match &self.predicate {
+            Some(predicate) => format!("response {} json {} {predicate}", self.exchange, self.path),
+            None => format!("response {} json {} is revealed", self.exchange, self.path),
+        };
+        let body = match response_body(output, self.exchange) {
+            Ok(body) => body,
+            Err(err) => return Verdict::fail(policy, err.to_string()),
+        };
+        let json = match body.json() {
+            Ok(json) => json,
+            Err(err) => return Verdict::fail(policy, format!("response body is not JSON: {err}")),
+        };
+
+        match json.lookup(&self.path) {
+            Lookup::Found(value) => {
+                let ranges = describe_ranges(&body.transcript_ranges(value.span.clone()));
+                if !value.is_fully_revealed() {
+                    return Verdict::fail(
+                        policy,
+                        format!("value at received bytes {ranges} is partially redacted"),
+                    );
+                }
+                match self
+                    .predicate
+                    .as_ref()
+                    .map(|predicate| predicate.test(value))
+                {
+                    Some(Err(reason)) => {
+                        Verdict::fail(policy, format!("{reason} at received bytes {ranges}"))
+                    }
+                    _ => Verdict::pass(policy)
+                        .with_reason(format!("found at received bytes {ranges}")),
+                }
+            }
+            Lookup::Redacted(value) => {
+                let ranges = describe_ranges(&body.transcript_ranges(value.span.clone()));
+                Verdict::fail(
+                    policy,
+                    format!("value is redacted at received bytes {ranges}"),
+                )
+            }
+            Lookup::Missing => Verdict::fail(policy, "value is missing"),
+        }
+    }
+}
+
+/// Checks that a response body matches a regular expression. Chunked bodies are de-chunked
+/// first, and matches are searched within each revealed run of the body, so they never include
+/// redacted bytes.
+pub struct ResponseMatches {
+    regex: Regex,
+    exchange: usize,
+}
+
+impl ResponseMatches {
+    /// Checks the body of the first response.
+    pub fn new(pattern: &str) -> Result<Self, eyre::ErrReport> {
+        let regex = Regex::new(pattern).map_err(|err| eyre!("Invalid regex {pattern:?}: {err}"))?;
+        Ok(Self { regex, exchange: 0 })
+    }
+
+    /// Checks the response of the exchange at `index` instead of the first one.
+    pub fn exchange(mut self, index: usize) -> Self {
+        self.exchange = index;
+        self
+    }
+}
+
+#[async_trait]
+impl TranscriptPolicy for ResponseMatches {
+    async fn check(&self, output: &VerifierOutput) -> Verdict {
+        let policy = format!(
+            "response {} body matches /{}/",
+            self.exchange,
+            self.regex.as_str()
+        );
+        let body = match response_body(output, self.exchange) {
+            Ok(body) => body,
+            Err(err) => return Verdict::fail(policy, err.to_string()),
+        };
+
+        let found = revealed_runs(&body).find_map(|run| {
+            self.regex
+                .find_at(&body.data[..run.end], run.start)
+                .map(|found| found.range())
+        });
+        match found {
+            Some(range) => {
+                let ranges = describe_ranges(&body.transcript_ranges(range));
+                Verdict::pass(policy).with_reason(format!("matched at received bytes {ranges}"))
+            }
+            None => Verdict::fail(policy, "no match in the revealed response body"),
+        }
+    }
+}
+
+/// An aggregate over the elements of a JSON array. Fields are dot-separated paths within each
+/// element, where an empty path is the element itself.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Aggregate {
+    Count,
+    Sum(String),
+    Min(String),
+    Max(String),
+}
+
+impl fmt::Display for Aggregate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Aggregate::Count => write!(f, "count"),
+            Aggregate::Sum(field) => write!(f, "sum of {field}"),
+            Aggregate::Min(field) => write!(f, "min of {field}"),
+            Aggregate::Max(field) => write!(f, "max of {field}"),
+        }
+    }
+}
+
+/// What to do with array elements whose fields read by the policy are not fully revealed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Partial {
+    /// Fails the policy.
+    #[default]
+    Reject,
+    /// Leaves the element out, so aggregates only cover the readable elements. A redacted run
+    /// in an array may hide several elements.
+    Skip,
+}
+
+/// Checks an aggregate over the elements of a JSON array, e.g. "at least 3 transactions over
+/// 100" is a [`Aggregate::Count`] at least 3, filtered on `amount` greater than 100.
+pub struct ResponseArray {
+    path: String,
+    filters: Vec<(String, Predicate)>,
+    aggregate: Aggregate,
+    predicate: Predicate,
+    partial: Partial,
+    exchange: usize,
+}
+
+impl ResponseArray {
+    /// Requires the `aggregate` over the array at `path` in the first response to satisfy
+    /// `predicate`. Elements that cannot be read fail the policy unless skipped with
+    /// [`ResponseArray::partial`].
+    pub fn new(path: impl Into<String>, aggregate: Aggregate, predicate: Predicate) -> Self {
+        Self {
+            path: path.into(),
+            filters: Vec::new(),
+            aggregate,
+            predicate,
+            partial: Partial::default(),
+            exchange: 0,
+        }
+    }
+
+    /// Only aggregates elements whose `field` satisfies `predicate`. Filters add up.
+    pub fn filter(mut self, field: impl Into<String>, predicate: Predicate) -> Self {
+        self.filters.push((field.into(), predicate));
+        self
+    }
+
+    pub fn partial(mut self, partial: Partial) -> Self {
+        self.partial = partial;
+        self
+    }
+
+    /// Checks the response of the exchange at `index` instead of the first one.
+    pub fn exchange(mut self, index: usize) -> Self {
+        self.exchange = index;
+        self
+    }
+
+    /// Computes the aggregate over `elems`, returning it with the number of matching and
+    /// skipped elements.
+    fn aggregate(&self, elems: &[Json]) -> Result<(Decimal, usize, usize), String> {
+        let field = match &self.aggregate {
+            Aggregate::Count => None,
+            Aggregate::Sum(field) | Aggregate::Min(field) | Aggregate::Max(field) => Some(field),
+        };
+
+        let mut matched = Vec::new();
+        let mut skipped = 0;
+        'elems: for (idx, elem) in elems.iter().enumerate() {
+            // `Err` if the field may hold a value that is not revealed.
+            let lookup = |field: &str| match elem.lookup(field) {
+                Lookup::Found(value) if value.is_fully_revealed() => Ok(Some(value)),
+                Lookup::Found(_) | Lookup::Redacted(_) => Err(()),
+                Lookup::Missing => Ok(None),
+            };
+
+            // Elements that revealed fields rule out are left out, whatever else is redacted.
+            let mut redacted = None;
+            for (filter, predicate) in &self.filters {
+                match lookup(filter) {
+                    Ok(value) if value.is_none_or(|value| predicate.test(value).is_err()) => {
+                        continue 'elems
+                    }
+                    Ok(_) => {}
+                    Err(()) => redacted = redacted.or(Some(filter)),
+                }
+            }
+            let value = match field.map(|field| (field, lookup(field))) {
+                Some((_, Ok(value))) => value,
+                Some((field, Err(()))) => {
+                    redacted = redacted.or(Some(field));
+                    None
+                }
+                None => None,
+            };
+
+            if redacted.is_some() || elem.is_redacted() {
+                match (self.partial, redacted) {
+                    (Partial::Skip, _) => {
+                        skipped += 1;
+                        continue;
+                    }
+                    (Partial::Reject, Some(field)) => {
+                        return Err(format!("element {idx} field {field:?} is redacted"))
+                    }
+                    (Partial::Reject, None) => return Err(format!("element {idx} is redacted")),
+                }
+            }
+            let value = match (field, value) {
+                (Some(_), Some(value)) => predicate::number(value)
+                    .ok_or_else(|| format!("element {idx} is not a number"))?,
+                (Some(field), None) => return Err(format!("element {idx} has no {field}")),
+                (None, _) => Decimal::ZERO,
+            };
+            matched.push(value);
+        }
+
+        let count = matched.len();
+        let result = match &self.aggregate {
+            Aggregate::Count => Decimal::from(count),
+            Aggregate::Sum(_) => matched
+                .into_iter()
+                .try_fold(Decimal::ZERO, Decimal::checked_add)
+                .ok_or("sum is out of range")?,
+            Aggregate::Min(_) => matched.into_iter().min().ok_or("no elements match")?,
+            Aggregate::Max(_) => matched.into_iter().max().ok_or("no elements match")?,
+        };
+        Ok((result, count, skipped))
+    }
+}
+
+#[async_trait]
+impl TranscriptPolicy for ResponseArray {
+    async fn check(&self, output: &VerifierOutput) -> Verdict {
+        let filters = self
+            .filters
+            .iter()
+            .map(|(field, predicate)| format!(" where {field} {predicate}"))
+            .collect::<String>();
+        let policy = format!(
+            "{} of response {} json {}{filters} {}",
+            self.aggregate, self.exchange, self.path, self.predicate
+        );
+        let body = match response_body(output, self.exchange) {
+            Ok(body) => body,
+            Err(err) => return Verdict::fail(policy, err.to_string()),
+        };
+        let json = match body.json() {
+            Ok(json) => json,
+            Err(err) => return Verdict::fail(policy, format!("response body is not JSON: {err}")),
+        };
+
+        let array = match json.lookup(&self.path) {
+            Lookup::Found(value) => value,
+            Lookup::Redacted(value) => {
+                let ranges = describe_ranges(&body.transcript_ranges(value.span.clone()));
+                return Verdict::fail(
+                    policy,
+                    format!("array is redacted at received bytes {ranges}"),
+                );
+            }
+            Lookup::Missing => return Verdict::fail(policy, "array is missing"),
+        };
+        let JsonKind::Array(elems) = &array.kind else {
+            return Verdict::fail(policy, "value is not an array");
+        };
+        let ranges = describe_ranges(&body.transcript_ranges(array.span.clone()));
+
+        let (result, count, skipped) = match self.aggregate(elems) {
+            Ok(aggregate) => aggregate,
+            Err(reason) => {
+                return Verdict::fail(
+                    policy,
+                    format!("{reason} in array at received bytes {ranges}"),
+                )
+            }
+        };
+        let summary =
+            format!("{count} matching elements, {skipped} skipped, at received bytes {ranges}");
+        let value = Json {
+            kind: JsonKind::Number(result.to_string()),
+            span: array.span.clone(),
+        };
+        match self.predicate.test(&value) {
+            Ok(()) => Verdict::pass(policy).with_reason(summary),
+            Err(reason) => Verdict::fail(policy, format!("{reason} over {summary}")),
+        }
+    }
+}
+
+/// Where the prover must put the verifier's nonce in its first request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NonceLocation {
+    /// A request header with this name.
+    Header(String),
+    /// A query parameter of the request target with this name.
+    Query(String),
+}
+
+/// Checks that the first request carries a nonce issued by the verifier, binding the session to
+/// the relying party's request so it cannot be reused.
+pub struct Nonce {
+    value: String,
+    location: NonceLocation,
+}
+
+impl Nonce {
+    pub fn new(value: impl Into<String>, location: NonceLocation) -> Self {
+        Self {
+            value: value.into(),
+            location,
+        }
+    }
+}
+
+#[async_trait]
+impl TranscriptPolicy for Nonce {
+    async fn check(&self, output: &VerifierOutput) -> Verdict {
+        let policy = match &self.location {
+            NonceLocation::Header(name) => format!("nonce in request header {name}"),
+            NonceLocation::Query(name) => format!("nonce in request query parameter {name}"),
+        };
+        let request = match output.transcript.as_ref() {
+            Some(transcript) => http::parse_request(&Revealed::sent(transcript), 0),
+            None => return Verdict::fail(policy, "no transcript data was revealed"),
+        };
+        let request = match request {
+            Ok(request) => request,
+            Err(err) => return Verdict::fail(policy, err.to_string()),
+        };
+
+        let value = match &self.location {
+            NonceLocation::Header(name) => match request.header(name) {
+                Some(header) => header.value_str(),
+                None => return Verdict::fail(policy, "nonce is missing"),
+            },
+            NonceLocation::Query(name) => match &request.target {
+                Some(target) => match query_param(target, name) {
+                    Some(value) => Some(value),
+                    None => return Verdict::fail(policy, "nonce is missing"),
+                },
+                None => None,
+            },
+        };
+        match value {
+            Some(value) if value == self.value => Verdict::pass(policy),
+            Some(_) => Verdict::fail(
+                policy,
+                "nonce does not match the one issued for this session",
+            ),
+            None => Verdict::fail(policy, "nonce is redacted"),
+        }
+    }
+}
+
+/// Returns the raw value of the query parameter `name` of a request target.
+fn query_param<'a>(target: &'a str, name: &str) -> Option<&'a str> {
+    let (_, query) = target.split_once('?')?;
+    let query = query.split('#').next().unwrap_or_default();
+    query.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        (key == name).then_some(value)
+    })
+}
+
+/// Checks that the session is recent, using the time the TLS connection was established.
+pub struct Freshness {
+    max_age: Duration,
+    max_skew: Duration,
+    date_header: bool,
+}
+
+impl Freshness {
+    /// Requires the connection to be at most `max_age` old.
+    pub fn new(max_age: Duration) -> Self {
+        Self {
+            max_age,
+            max_skew: Duration::ZERO,
+            date_header: false,
+        }
+    }
+
+    /// Allows the connection time to be off the verifier's clock by `max_skew`.
+    pub fn max_skew(mut self, max_skew: Duration) -> Self {
+        self.max_skew = max_skew;
+        self
+    }
+
+    /// Requires every revealed `Date` response header to agree with the connection time, within
+    /// the allowed skew. Redacted headers are skipped.
+    pub fn date_header(mut self) -> Self {
+        self.date_header = true;
+        self
+    }
+
+    fn check_time(&self, time: SystemTime, now: SystemTime) -> Result<(), String> {
+        match now.duration_since(time) {
+            Ok(age) if age > self.max_age + self.max_skew => {
+                Err(format!("connection is {}s old", age.as_secs()))
+            }
+            Err(err) if err.duration() > self.max_skew => Err(format!(
+                "connection time is {}s in the future",
+                err.duration().as_secs()
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_date(&self, date: &str, time: SystemTime) -> Result<(), String> {
+        let parsed =
+            httpdate::parse_http_date(date).map_err(|_| format!("invalid Date header {date:?}"))?;
+        let skew = parsed
+            .duration_since(time)
+            .unwrap_or_else(|err| err.duration());
+        // Dates have a resolution of one second.
+        if skew > self.max_skew + Duration::from_secs(1) {
+            return Err(format!(
+                "Date header {date:?} is {}s off the connection time",
+                skew.as_secs()
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TranscriptPolicy for Freshness {
+    async fn check(&self, output: &VerifierOutput) -> Verdict {
+        let mut policy = format!("connection is at most {}s old", self.max_age.as_secs());
+        if self.date_header {
+            policy.push_str(" and agrees with Date headers");
+        }
+        let time = connection_time(output);
+        if let Err(reason) = self.check_time(time, SystemTime::now()) {
+            return Verdict::fail(policy, reason);
+        }
+        let connected = format!("connected at {}", httpdate::fmt_http_date(time));
+        if !self.date_header {
+            return Verdict::pass(policy).with_reason(connected);
+        }
+
+        let exchanges = match exchanges(output) {
+            Ok(exchanges) => exchanges,
+            Err(err) => return Verdict::fail(policy, err.to_string()),
+        };
+        let mut checked = 0;
+        for (idx, exchange) in exchanges.iter().enumerate() {
+            let Some(date) = exchange
+                .response
+                .header("date")
+                .and_then(|header| header.value_str())
+            else {
+                continue;
+            };
+            if let Err(reason) = self.check_date(date, time) {
+                return Verdict::fail(policy, format!("exchange {idx}: {reason}"));
+            }
+            checked += 1;
+        }
+        Verdict::pass(policy).with_reason(format!("{connected}, {checked} Date headers agree"))
+    }
+}
+
+/// Time the TLS connection of the session was established.
+pub fn connection_time(output: &VerifierOutput) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(output.connection_info.time)
+}
+
+pub(crate) fn exchanges(output: &VerifierOutput) -> Result<Vec<Exchange>, eyre::ErrReport> {
+    let transcript = output
+        .transcript
+        .as_ref()
+        .ok_or_else(|| eyre!("no transcript data was revealed"))?;
+    http::parse_exchanges(&Revealed::sent(transcript), &Revealed::received(transcript))
+}
+
+fn response_body(output: &VerifierOutput, exchange: usize) -> Result<Body, eyre::ErrReport> {
+    let exchanges = exchanges(output)?;
+    let exchange = exchanges
+        .into_iter()
+        .nth(exchange)
+        .ok_or_else(|| eyre!("session has no exchange {exchange}"))?;
+    exchange
+        .response
+        .body
+        .ok_or_else(|| eyre!("response has no body"))
+}
+
+/// Maximal runs of revealed bytes in the body.
+fn revealed_runs(body: &Body) -> impl Iterator<Item = Range<usize>> + '_ {
+    let mut start = 0;
+    std::iter::from_fn(move || {
+        start += body.revealed[start..]
+            .iter()
+            .position(|&revealed| revealed)?;
+        let len = body.revealed[start..]
+            .iter()
+            .position(|&revealed| !revealed)
+            .unwrap_or(body.len() - start);
+        let run = start..start + len;
+        start = run.end;
+        Some(run)
+    })
+}
+
+fn describe_ranges(ranges: &[Range<usize>]) -> String {
+    ranges
+        .iter()
+        .map(|range| format!("{}..{}", range.start, range.end))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_byte_patterns_in_revealed_data() {
+        let data = [0x00, 0x1f, 0x8b, 0x08, 0x00, 0x1f, 0x8b, 0x08];
+        let pattern = BytePattern::hex("1f 8b ??").unwrap();
+
+        // The first match is partially redacted.
+        assert_eq!(pattern.find(&Revealed::new(&data, [0..3, 5..8])), Some(5));
+        assert_eq!(pattern.find(&Revealed::new(&data, [0..4, 4..7])), Some(1));
+        assert_eq!(pattern.find(&Revealed::new(&data, [0..1, 1..2])), None);
+        assert_eq!(pattern.to_string(), "1f8b??");
+        assert_eq!(BytePattern::literal("Host").to_string(), "\"Host\"");
+        assert!(BytePattern::hex("1f8").is_err());
+        assert!(BytePattern::hex("zz").is_err());
+    }
+
+    #[test]
+    fn aggregates_json_arrays() {
+        let text =
+            r#"[{"amount": 150.5}, {"amount": 99, "id": 7}, {"amount": "200"}, {"amount": 120}]"#;
+        // The amount of the last element is redacted, and so is the id of one filtered out.
+        let redacted = text.rfind("120").unwrap();
+        let mut revealed = vec![true; text.len()];
+        revealed[redacted..redacted + 3].fill(false);
+        let id = text.find('7').unwrap();
+        revealed[id] = false;
+        let json = Json::parse(text.as_bytes(), &revealed).unwrap();
+        let JsonKind::Array(elems) = &json.kind else {
+            panic!("not an array");
+        };
+
+        let over_100 = |aggregate| {
+            ResponseArray::new("", aggregate, Predicate::at_least("0").unwrap())
+                .filter("amount", Predicate::greater_than("100").unwrap())
+        };
+        assert_eq!(
+            over_100(Aggregate::Count).aggregate(elems),
+            Err("element 3 field \"amount\" is redacted".into())
+        );
+        let skip = |aggregate| over_100(aggregate).partial(Partial::Skip).aggregate(elems);
+        assert_eq!(skip(Aggregate::Count), Ok((Decimal::from(2), 2, 1)));
+        assert_eq!(
+            skip(Aggregate::Sum("amount".into())),
+            Ok(("350.5".parse().unwrap(), 2, 1))
+        );
+        assert_eq!(
+            skip(Aggregate::Min("amount".into())),
+            Ok(("150.5".parse().unwrap(), 2, 1))
+        );
+    }
+
+    #[test]
+    fn finds_query_parameters() {
+        assert_eq!(query_param("/a?x=1&nonce=abc#f", "nonce"), Some("abc"));
+        assert_eq!(query_param("/a?nonce", "nonce"), Some(""));
+        assert_eq!(query_param("/a?xnonce=1", "nonce"), None);
+        assert_eq!(query_param("/a", "nonce"), None);
+    }
+
+    #[test]
+    fn checks_freshness() {
+        let freshness = Freshness::new(Duration::from_secs(300)).max_skew(Duration::from_secs(30));
+        let time = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+        assert!(freshness
+            .check_time(time, time + Duration::from_secs(320))
+            .is_ok());
+        assert!(freshness
+            .check_time(time, time + Duration::from_secs(331))
+            .is_err());
+        assert!(freshness
+            .check_time(time + Duration::from_secs(31), time)
+            .is_err());
+
+        // 1_700_000_000 is Tue, 14 Nov 2023 22:13:20 GMT.
+        assert!(freshness
+            .check_date("Tue, 14 Nov 2023 22:13:50 GMT", time)
+            .is_ok());
+        assert!(freshness
+            .check_date("Tue, 14 Nov 2023 22:12:40 GMT", time)
+            .is_err());
+        assert!(freshness.check_date("yesterday", time).is_err());
+    }
+}
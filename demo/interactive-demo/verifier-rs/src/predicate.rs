@@ -0,0 +1,404 @@
+//! Typed predicates on revealed JSON values.
+//!
+//! Numbers are compared exactly, using their decimal literals rather than floating point, so
+//! `0.1 + 0.2`-style rounding never decides a verdict. Dates are RFC 3339 timestamps, plain
+//! `YYYY-MM-DD` dates (midnight UTC) or Unix timestamps in seconds.
+use chrono::{DateTime, NaiveDate, Utc};
+use eyre::eyre;
+use serde_json::Value;
+use std::{cmp::Ordering, fmt};
+
+use crate::json::{Json, JsonKind};
+
+/// A condition on a JSON value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Predicate {
+    Equals(Value),
+    /// The value is a number, or a string holding one, ordered against a bound.
+    Compare(Comparison, Decimal),
+    /// The value is a date strictly before the bound.
+    Before(DateTime<Utc>),
+    /// The value is a date strictly after the bound.
+    After(DateTime<Utc>),
+    /// The value equals one of the listed values.
+    OneOf(Vec<Value>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Predicate {
+    pub fn equals(value: impl Into<Value>) -> Self {
+        Predicate::Equals(value.into())
+    }
+
+    /// Requires a number less than `bound`, a JSON number literal such as `"1000.50"`.
+    pub fn less_than(bound: &str) -> Result<Self, eyre::ErrReport> {
+        Ok(Predicate::Compare(Comparison::Less, bound.parse()?))
+    }
+
+    pub fn at_most(bound: &str) -> Result<Self, eyre::ErrReport> {
+        Ok(Predicate::Compare(Comparison::LessOrEqual, bound.parse()?))
+    }
+
+    pub fn greater_than(bound: &str) -> Result<Self, eyre::ErrReport> {
+        Ok(Predicate::Compare(Comparison::Greater, bound.parse()?))
+    }
+
+    pub fn at_least(bound: &str) -> Result<Self, eyre::ErrReport> {
+        Ok(Predicate::Compare(
+            Comparison::GreaterOrEqual,
+            bound.parse()?,
+        ))
+    }
+
+    /// Requires a date before `bound`, e.g. `"2024-01-01"`.
+    pub fn before(bound: &str) -> Result<Self, eyre::ErrReport> {
+        Ok(Predicate::Before(parse_date(bound)?))
+    }
+
+    pub fn after(bound: &str) -> Result<Self, eyre::ErrReport> {
+        Ok(Predicate::After(parse_date(bound)?))
+    }
+
+    pub fn one_of(values: impl IntoIterator<Item = impl Into<Value>>) -> Self {
+        Predicate::OneOf(values.into_iter().map(Into::into).collect())
+    }
+
+    /// Tests a fully revealed value, returning why it does not satisfy the predicate.
+    pub fn test(&self, value: &Json) -> Result<(), String> {
+        let actual = value
+            .to_value()
+            .ok_or_else(|| "value is partially redacted".to_string())?;
+        let satisfied = match self {
+            Predicate::Equals(expected) => equal(value, &actual, expected),
+            Predicate::OneOf(expected) => expected
+                .iter()
+                .any(|expected| equal(value, &actual, expected)),
+            Predicate::Compare(comparison, bound) => {
+                let number = number(value).ok_or_else(|| format!("{actual} is not a number"))?;
+                let ordering = number.cmp(bound);
+                match comparison {
+                    Comparison::Less => ordering.is_lt(),
+                    Comparison::LessOrEqual => ordering.is_le(),
+                    Comparison::Greater => ordering.is_gt(),
+                    Comparison::GreaterOrEqual => ordering.is_ge(),
+                }
+            }
+            Predicate::Before(bound) | Predicate::After(bound) => {
+                let date = date(value).ok_or_else(|| format!("{actual} is not a date"))?;
+                match self {
+                    Predicate::Before(_) => date < *bound,
+                    _ => date > *bound,
+                }
+            }
+        };
+        if satisfied {
+            Ok(())
+        } else {
+            Err(format!("found {actual}"))
+        }
+    }
+}
+
+impl fmt::Display for Predicate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Predicate::Equals(value) => write!(f, "is {value}"),
+            Predicate::Compare(comparison, bound) => {
+                let op = match comparison {
+                    Comparison::Less => "<",
+                    Comparison::LessOrEqual => "<=",
+                    Comparison::Greater => ">",
+                    Comparison::GreaterOrEqual => ">=",
+                };
+                write!(f, "{op} {bound}")
+            }
+            Predicate::Before(date) => write!(f, "before {}", date.to_rfc3339()),
+            Predicate::After(date) => write!(f, "after {}", date.to_rfc3339()),
+            Predicate::OneOf(values) => {
+                let values = values.iter().map(Value::to_string).collect::<Vec<_>>();
+                write!(f, "in {{{}}}", values.join(", "))
+            }
+        }
+    }
+}
+
+/// A decimal number, `mantissa * 10^-scale`, exact for any JSON literal whose digits fit in an
+/// `i128`.
+#[derive(Clone, Copy, Debug)]
+pub struct Decimal {
+    mantissa: i128,
+    scale: u32,
+}
+
+impl Decimal {
+    pub const ZERO: Decimal = Decimal {
+        mantissa: 0,
+        scale: 0,
+    };
+
+    /// Adds two decimals, or returns `None` on overflow.
+    pub fn checked_add(self, other: Decimal) -> Option<Decimal> {
+        let scale = self.scale.max(other.scale);
+        let mantissa = self.rescale(scale)?.checked_add(other.rescale(scale)?)?;
+        Some(Decimal { mantissa, scale }.normalize())
+    }
+
+    /// The mantissa at a larger `scale`.
+    fn rescale(self, scale: u32) -> Option<i128> {
+        10i128
+            .checked_pow(scale - self.scale)?
+            .checked_mul(self.mantissa)
+    }
+
+    /// Strips trailing zeros, so equal values have equal representations.
+    fn normalize(mut self) -> Self {
+        if self.mantissa == 0 {
+            return Decimal::ZERO;
+        }
+        while self.scale > 0 && self.mantissa % 10 == 0 {
+            self.mantissa /= 10;
+            self.scale -= 1;
+        }
+        self
+    }
+}
+
+impl From<usize> for Decimal {
+    fn from(value: usize) -> Self {
+        Decimal {
+            mantissa: value as i128,
+            scale: 0,
+        }
+    }
+}
+
+impl std::str::FromStr for Decimal {
+    type Err = eyre::ErrReport;
+
+    fn from_str(literal: &str) -> Result<Self, Self::Err> {
+        let invalid = || eyre!("Invalid number {literal:?}");
+        let (negative, unsigned) = match literal.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, literal),
+        };
+        let (significand, exponent) = match unsigned.split_once(['e', 'E']) {
+            Some((significand, exponent)) => (
+                significand,
+                exponent
+                    .strip_prefix('+')
+                    .unwrap_or(exponent)
+                    .parse::<i32>()
+                    .map_err(|_| invalid())?,
+            ),
+            None => (unsigned, 0),
+        };
+        let (int, frac) = significand.split_once('.').unwrap_or((significand, ""));
+        let is_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+        if int.is_empty() || !is_digits(int) || !is_digits(frac) || significand.ends_with('.') {
+            return Err(invalid());
+        }
+        let frac = frac.trim_end_matches('0');
+
+        let mut mantissa = 0i128;
+        for digit in int.bytes().chain(frac.bytes()) {
+            mantissa = mantissa
+                .checked_mul(10)
+                .and_then(|m| m.checked_add(i128::from(digit - b'0')))
+                .ok_or_else(|| eyre!("Number {literal:?} has too many digits"))?;
+        }
+        // Zero has no scale, however large its exponent.
+        if mantissa == 0 {
+            return Ok(Decimal::ZERO);
+        }
+        if negative {
+            mantissa = -mantissa;
+        }
+
+        let scale = frac.len() as i64 - i64::from(exponent);
+        let decimal = if scale >= 0 {
+            Decimal {
+                mantissa,
+                scale: u32::try_from(scale).map_err(|_| invalid())?,
+            }
+        } else {
+            let factor = u32::try_from(-scale)
+                .ok()
+                .and_then(|exp| 10i128.checked_pow(exp));
+            match factor {
+                Some(factor) => Decimal {
+                    mantissa: mantissa
+                        .checked_mul(factor)
+                        .ok_or_else(|| eyre!("Number {literal:?} is out of range"))?,
+                    scale: 0,
+                },
+                None => return Err(eyre!("Number {literal:?} is out of range")),
+            }
+        };
+        Ok(decimal.normalize())
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let scale = self.scale.max(other.scale);
+        match (self.rescale(scale), other.rescale(scale)) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            // Overflowing when scaled up means the magnitude is beyond the other value's.
+            (None, _) => 0.cmp(&self.mantissa).reverse(),
+            (_, None) => 0.cmp(&other.mantissa),
+        }
+    }
+}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for Decimal {}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let digits = self.mantissa.unsigned_abs().to_string();
+        let sign = if self.mantissa < 0 { "-" } else { "" };
+        let scale = self.scale as usize;
+        if scale == 0 {
+            write!(f, "{sign}{digits}")
+        } else if digits.len() > scale {
+            let (int, frac) = digits.split_at(digits.len() - scale);
+            write!(f, "{sign}{int}.{frac}")
+        } else {
+            write!(f, "{sign}0.{digits:0>scale$}")
+        }
+    }
+}
+
+/// The number in a revealed value, which may also be a string such as `"1000.50"`.
+pub fn number(value: &Json) -> Option<Decimal> {
+    match &value.kind {
+        JsonKind::Number(literal) | JsonKind::String(literal) => literal.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+/// Whether `value`, revealed as `actual`, equals `expected`. Numbers are compared by value, so
+/// `1e3` equals `1000` and `1.50` equals `1.5`.
+fn equal(value: &Json, actual: &Value, expected: &Value) -> bool {
+    if let (JsonKind::Number(literal), Value::Number(number)) = (&value.kind, expected) {
+        if let (Ok(literal), Ok(number)) = (
+            literal.parse::<Decimal>(),
+            number.to_string().parse::<Decimal>(),
+        ) {
+            return literal == number;
+        }
+    }
+    actual == expected
+}
+
+/// The date in a revealed value.
+pub fn date(value: &Json) -> Option<DateTime<Utc>> {
+    match &value.kind {
+        JsonKind::String(text) => parse_date(text).ok(),
+        JsonKind::Number(literal) => DateTime::from_timestamp(literal.parse().ok()?, 0),
+        _ => None,
+    }
+}
+
+fn parse_date(text: &str) -> Result<DateTime<Utc>, eyre::ErrReport> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(text) {
+        return Ok(date.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(text, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|date| date.and_utc())
+        .ok_or_else(|| eyre!("Invalid date {text:?}, expected RFC 3339 or YYYY-MM-DD"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn json(text: &str) -> Json {
+        Json::parse(text.as_bytes(), &vec![true; text.len()]).unwrap()
+    }
+
+    fn decimal(literal: &str) -> Decimal {
+        literal.parse().unwrap()
+    }
+
+    #[test]
+    fn compares_decimals_exactly() {
+        assert_eq!(decimal("1000"), decimal("1e3"));
+        assert_eq!(decimal("1000.50"), decimal("100050E-2"));
+        assert!(decimal("0.30000000000000001") > decimal("0.3"));
+        assert!(decimal("-2.5") < decimal("-2.49"));
+        assert!(decimal("1e30") > decimal("0.000000000000000000000000000001"));
+        assert!(decimal("9007199254740993") > decimal("9007199254740992"));
+        assert_eq!(
+            decimal("0.1").checked_add(decimal("0.2")),
+            Some(decimal("0.3"))
+        );
+        assert_eq!(decimal("-0.05").to_string(), "-0.05");
+        assert_eq!(decimal("0e-2147483648"), Decimal::ZERO);
+        assert_eq!(decimal("-0.000e99999"), Decimal::ZERO);
+        assert_eq!(
+            decimal("10e-2147483648").checked_add(decimal("-1e-2147483647")),
+            Some(Decimal::ZERO)
+        );
+        assert!("1.".parse::<Decimal>().is_err());
+        assert!("0x10".parse::<Decimal>().is_err());
+    }
+
+    #[test]
+    fn compares_equal_numbers_by_value() {
+        assert_eq!(Predicate::equals(1000).test(&json("1e3")), Ok(()));
+        assert_eq!(Predicate::equals(1.5).test(&json("1.50")), Ok(()));
+        assert_eq!(Predicate::one_of([1, 1000]).test(&json("1000.0")), Ok(()));
+        assert_eq!(
+            Predicate::equals(1.5).test(&json("1.51")),
+            Err("found 1.51".into())
+        );
+        // Strings holding numbers are not numbers.
+        assert!(Predicate::equals(1000).test(&json("\"1000\"")).is_err());
+    }
+
+    #[test]
+    fn tests_typed_predicates() {
+        let at_least = Predicate::at_least("1000").unwrap();
+        assert_eq!(at_least.test(&json("1000.00")), Ok(()));
+        assert_eq!(at_least.test(&json("\"1500\"")), Ok(()));
+        assert_eq!(at_least.test(&json("999.99")), Err("found 999.99".into()));
+        assert_eq!(
+            at_least.test(&json("true")),
+            Err("true is not a number".into())
+        );
+
+        let before = Predicate::before("2024-01-01").unwrap();
+        assert_eq!(before.test(&json("\"2023-12-31T23:59:59Z\"")), Ok(()));
+        assert!(before.test(&json("\"2023-12-31T23:59:59-01:00\"")).is_err());
+        assert_eq!(before.test(&json("1700000000")), Ok(()));
+
+        let status = Predicate::one_of(["active", "verified"]);
+        assert_eq!(status.test(&json("\"verified\"")), Ok(()));
+        assert_eq!(
+            status.test(&json("\"closed\"")),
+            Err("found \"closed\"".into())
+        );
+        assert_eq!(status.to_string(), "in {\"active\", \"verified\"}");
+    }
+}
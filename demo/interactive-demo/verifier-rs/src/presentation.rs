@@ -0,0 +1,235 @@
+//! Verification of notarized presentations in tlsn-js's `PresentationJSON` format.
+//!
+//! Browsers running tlsn-js (or the Rust prover's notarization mode) produce a JSON envelope
+//! holding the hex-encoded, bincode-serialized presentation. A presentation is only as
+//! trustworthy as the notary that signed its attestation, so it is accepted only if signed by
+//! one of the given notary keys.
+use base64::engine::{general_purpose::STANDARD, Engine as _};
+use eyre::eyre;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tlsn_core::{
+    presentation::{Presentation, PresentationOutput},
+    transcript::PartialTranscript,
+    CryptoProvider,
+};
+
+/// Versions of `PresentationJSON` whose data this verifier can decode, that of the tlsn it is
+/// built with.
+pub const SUPPORTED_VERSIONS: &[&str] = &["0.1.0-alpha.12"];
+
+/// A presentation in tlsn-js's `PresentationJSON` format.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PresentationJson {
+    pub version: String,
+    /// The bincode-serialized presentation, hex encoded.
+    pub data: String,
+    #[serde(default)]
+    pub meta: PresentationMeta,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PresentationMeta {
+    pub notary_url: Option<String>,
+    pub websocket_proxy_url: Option<String>,
+    pub plugin_url: Option<String>,
+}
+
+impl PresentationJson {
+    pub fn from_json(json: &str) -> Result<Self, eyre::ErrReport> {
+        serde_json::from_str(json).map_err(|err| eyre!("Invalid presentation JSON: {err}"))
+    }
+
+    /// Decodes the presentation, if its version is supported.
+    pub fn presentation(&self) -> Result<Presentation, eyre::ErrReport> {
+        if !SUPPORTED_VERSIONS.contains(&self.version.as_str()) {
+            return Err(eyre!(
+                "Unsupported presentation version {}, expected one of {}",
+                self.version,
+                SUPPORTED_VERSIONS.join(", ")
+            ));
+        }
+        let data =
+            decode_hex(&self.data).map_err(|err| eyre!("Invalid presentation data: {err}"))?;
+        bincode::deserialize(&data).map_err(|err| eyre!("Invalid presentation data: {err}"))
+    }
+
+    /// Verifies the presentation, accepting it only if its attestation is signed by one of
+    /// `trusted` notary keys.
+    pub fn verify(&self, trusted: &[NotaryKey]) -> Result<VerifiedPresentation, eyre::ErrReport> {
+        let presentation = self.presentation()?;
+        let key = NotaryKey(presentation.verifying_key().data.clone());
+        if !trusted.contains(&key) {
+            return Err(eyre!(
+                "Presentation is signed by untrusted notary key {key}"
+            ));
+        }
+
+        let PresentationOutput {
+            server_name,
+            connection_info,
+            transcript,
+            ..
+        } = presentation
+            .verify(&CryptoProvider::default())
+            .map_err(|err| eyre!("Invalid presentation: {err}"))?;
+
+        Ok(VerifiedPresentation {
+            notary_key: key,
+            server_name: server_name.map(|name| name.as_str().to_string()),
+            connection_time: UNIX_EPOCH + Duration::from_secs(connection_info.time),
+            transcript,
+        })
+    }
+}
+
+/// The data proven by a verified presentation.
+#[derive(Debug)]
+pub struct VerifiedPresentation {
+    /// Key of the notary that signed the attestation.
+    pub notary_key: NotaryKey,
+    /// Server name, if the prover revealed it.
+    pub server_name: Option<String>,
+    /// Time the TLS connection was established.
+    pub connection_time: SystemTime,
+    /// The transcript, with redacted parts, if any part of it was revealed.
+    pub transcript: Option<PartialTranscript>,
+}
+
+/// A notary's public key, as the bytes of a compressed elliptic curve point.
+#[derive(Clone, PartialEq, Eq)]
+pub struct NotaryKey(Vec<u8>);
+
+impl NotaryKey {
+    /// Parses a key as tlsn-js's `NotaryServer.publicKey()` returns it, in hex, or as PEM, as a
+    /// notary's `/info` endpoint does.
+    pub fn parse(key: &str) -> Result<Self, eyre::ErrReport> {
+        let key = key.trim();
+        if key.starts_with("-----BEGIN PUBLIC KEY-----") {
+            return Self::from_pem(key);
+        }
+        let bytes = decode_hex(key).map_err(|err| eyre!("Invalid notary key: {err}"))?;
+        Ok(Self(bytes))
+    }
+
+    fn from_pem(pem: &str) -> Result<Self, eyre::ErrReport> {
+        let body = pem
+            .lines()
+            .filter(|line| !line.starts_with("-----"))
+            .collect::<String>();
+        let der = STANDARD
+            .decode(body.trim())
+            .map_err(|err| eyre!("Invalid notary key: {err}"))?;
+        // A SubjectPublicKeyInfo ending with a bit string holding a compressed point: tag,
+        // length 34, no unused bits, then the 33 bytes of the point.
+        match der.len().checked_sub(36).map(|idx| der.split_at(idx).1) {
+            Some([0x03, 0x22, 0x00, point @ ..]) => Ok(Self(point.to_vec())),
+            _ => Err(eyre!(
+                "Invalid notary key: expected a compressed elliptic curve public key"
+            )),
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl fmt::Display for NotaryKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|byte| write!(f, "{byte:02x}"))
+    }
+}
+
+impl fmt::Debug for NotaryKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "NotaryKey({self})")
+    }
+}
+
+fn decode_hex(hex: &str) -> Result<Vec<u8>, String> {
+    let hex = hex.strip_prefix("0x").unwrap_or(hex);
+    hex.as_bytes()
+        .chunks(2)
+        .enumerate()
+        .map(|(idx, digits)| {
+            std::str::from_utf8(digits)
+                .ok()
+                .filter(|digits| digits.len() == 2)
+                .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                .ok_or_else(|| format!("invalid hex at offset {}", idx * 2))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A compressed secp256k1 point, as tlsn-js gives notary keys.
+    const KEY_HEX: &str = "0373aeb0a0e9cfc5a18d5bbc0b6e6a2b9d27df2d2e5e0e0b5e1c9e4e1a2b3c4d5e";
+
+    #[test]
+    fn parses_envelopes_and_keys() {
+        let json = PresentationJson::from_json(
+            r#"{"version": "0.1.0-alpha.7", "data": "0a", "meta": {"notaryUrl": "http://localhost:7047"}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            json.meta.notary_url.as_deref(),
+            Some("http://localhost:7047")
+        );
+        assert!(json
+            .presentation()
+            .unwrap_err()
+            .to_string()
+            .contains("Unsupported presentation version 0.1.0-alpha.7"));
+        assert!(PresentationJson::from_json(r#"{"version": "0.1.0-alpha.12"}"#).is_err());
+
+        assert_eq!(decode_hex("0x0aFf"), Ok(vec![0x0a, 0xff]));
+        assert!(decode_hex("0a1").is_err());
+        assert!(decode_hex("zz").is_err());
+
+        let key = NotaryKey::parse(KEY_HEX).unwrap();
+        assert_eq!(key.to_string(), KEY_HEX);
+
+        let mut der = vec![
+            0x30, 0x36, 0x30, 0x10, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06,
+            0x05, 0x2b, 0x81, 0x04, 0x00, 0x0a, 0x03, 0x22, 0x00,
+        ];
+        der.extend_from_slice(key.as_bytes());
+        let pem = format!(
+            "-----BEGIN PUBLIC KEY-----\n{}\n-----END PUBLIC KEY-----\n",
+            STANDARD.encode(&der)
+        );
+        assert_eq!(NotaryKey::parse(&pem).unwrap(), key);
+        assert!(
+            NotaryKey::parse("-----BEGIN PUBLIC KEY-----\nAAAA\n-----END PUBLIC KEY-----").is_err()
+        );
+    }
+
+    #[test]
+    #[ignore = "needs the fixtures described in fixtures/README.md"]
+    fn verifies_notarized_presentations() {
+        let fixtures = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures");
+        let read = |name: &str| std::fs::read_to_string(fixtures.join(name)).unwrap();
+        let json = PresentationJson::from_json(&read("presentation.json")).unwrap();
+        let trusted = NotaryKey::parse(&read("notary.pem")).unwrap();
+
+        let verified = json.verify(std::slice::from_ref(&trusted)).unwrap();
+        assert_eq!(verified.notary_key, trusted);
+        assert_eq!(
+            verified.server_name.as_deref(),
+            Some("raw.githubusercontent.com")
+        );
+        assert!(verified.transcript.is_some());
+
+        let other = NotaryKey::parse(KEY_HEX).unwrap();
+        let err = json.verify(&[other]).unwrap_err();
+        assert!(err.to_string().contains("untrusted notary key"), "{err}");
+    }
+}
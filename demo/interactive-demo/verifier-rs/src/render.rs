@@ -0,0 +1,275 @@
+//! Rendering of the revealed transcript, driven by the authenticated ranges.
+//!
+//! A transcript is split into [`Segment`]s of revealed bytes and redacted spans, which can be
+//! rendered as JSON, as text or hex with configurable markers, or highlighted for a terminal.
+//! Revealed data is kept as bytes, so binary payloads render without loss in hex or base64.
+use base64::engine::{general_purpose::STANDARD, Engine as _};
+use serde_json::{json, Value};
+use std::fmt::Write;
+
+use crate::transcript::Revealed;
+
+/// A run of revealed bytes or a redacted span of one direction of the transcript.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Segment {
+    Revealed {
+        offset: usize,
+        length: usize,
+        data: Vec<u8>,
+    },
+    Redacted {
+        offset: usize,
+        length: usize,
+    },
+}
+
+impl Segment {
+    /// The segment as a JSON object, with revealed data in `encoding`.
+    pub fn to_json(&self, encoding: Encoding) -> Value {
+        match self {
+            Segment::Revealed {
+                offset,
+                length,
+                data,
+            } => json!({
+                "kind": "revealed",
+                "offset": offset,
+                "length": length,
+                "data": encoding.encode(data),
+            }),
+            Segment::Redacted { offset, length } => json!({
+                "kind": "redacted",
+                "offset": offset,
+                "length": length,
+            }),
+        }
+    }
+}
+
+/// Splits one direction of the transcript into revealed and redacted segments.
+pub fn segments(revealed: &Revealed<'_>) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let mut offset = 0;
+    for range in revealed.authed() {
+        if range.start > offset {
+            segments.push(Segment::Redacted {
+                offset,
+                length: range.start - offset,
+            });
+        }
+        segments.push(Segment::Revealed {
+            offset: range.start,
+            length: range.len(),
+            data: revealed.data()[range.clone()].to_vec(),
+        });
+        offset = range.end;
+    }
+    if offset < revealed.len() {
+        segments.push(Segment::Redacted {
+            offset,
+            length: revealed.len() - offset,
+        });
+    }
+    segments
+}
+
+/// How redacted spans are shown in text.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Marker {
+    /// Repeat the marker for every redacted byte, e.g. `🙈🙈🙈`.
+    PerByte(String),
+    /// Show the marker once per span. `{len}` is replaced by the span length, e.g.
+    /// `[{len} bytes redacted]`.
+    PerSpan(String),
+}
+
+impl Default for Marker {
+    fn default() -> Self {
+        Marker::PerByte("🙈".to_string())
+    }
+}
+
+impl Marker {
+    fn render(&self, length: usize) -> String {
+        match self {
+            Marker::PerByte(marker) => marker.repeat(length),
+            Marker::PerSpan(template) => template.replace("{len}", &length.to_string()),
+        }
+    }
+}
+
+/// How revealed bytes are encoded as a string.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Encoding {
+    /// UTF-8, with invalid sequences replaced by `U+FFFD`.
+    #[default]
+    Utf8Lossy,
+    /// Lowercase hex, two digits per byte.
+    Hex,
+    /// Standard base64 with padding.
+    Base64,
+}
+
+impl Encoding {
+    pub fn encode(&self, data: &[u8]) -> String {
+        match self {
+            Encoding::Utf8Lossy => String::from_utf8_lossy(data).into_owned(),
+            Encoding::Hex => hex(data),
+            Encoding::Base64 => STANDARD.encode(data),
+        }
+    }
+}
+
+fn hex(data: &[u8]) -> String {
+    let mut text = String::with_capacity(data.len() * 2);
+    for byte in data {
+        let _ = write!(text, "{byte:02x}");
+    }
+    text
+}
+
+/// Output format of the rendered transcript.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Rendering {
+    /// Plain text, with redacted spans replaced by the marker.
+    Text(Marker),
+    /// Text for terminals: redacted spans are highlighted and control characters escaped.
+    Ansi(Marker),
+    /// Hex, with redacted spans replaced by the marker. Suits binary payloads.
+    Hex(Marker),
+    /// The segment list as JSON, with revealed data in the given encoding.
+    Json(Encoding),
+}
+
+impl Default for Rendering {
+    fn default() -> Self {
+        Rendering::Text(Marker::default())
+    }
+}
+
+impl Rendering {
+    pub fn render(&self, segments: &[Segment]) -> String {
+        match self {
+            Rendering::Text(marker) => render_text(segments, marker, Encoding::Utf8Lossy),
+            Rendering::Ansi(marker) => render_ansi(segments, marker),
+            Rendering::Hex(marker) => render_text(segments, marker, Encoding::Hex),
+            Rendering::Json(encoding) => Value::Array(
+                segments
+                    .iter()
+                    .map(|segment| segment.to_json(*encoding))
+                    .collect(),
+            )
+            .to_string(),
+        }
+    }
+}
+
+fn render_text(segments: &[Segment], marker: &Marker, encoding: Encoding) -> String {
+    let mut text = String::new();
+    for segment in segments {
+        match segment {
+            Segment::Revealed { data, .. } => text.push_str(&encoding.encode(data)),
+            Segment::Redacted { length, .. } => text.push_str(&marker.render(*length)),
+        }
+    }
+    text
+}
+
+const ANSI_REDACTED: &str = "\x1b[7;2m";
+const ANSI_ESCAPED: &str = "\x1b[33m";
+const ANSI_RESET: &str = "\x1b[0m";
+
+fn render_ansi(segments: &[Segment], marker: &Marker) -> String {
+    let mut text = String::new();
+    for segment in segments {
+        match segment {
+            Segment::Revealed { data, .. } => {
+                // Escape control characters so revealed data cannot drive the terminal.
+                for c in String::from_utf8_lossy(data).chars() {
+                    match c {
+                        '\n' | '\t' => text.push(c),
+                        '\r' => {}
+                        c if c.is_control() => {
+                            let _ =
+                                write!(text, "{ANSI_ESCAPED}{}{ANSI_RESET}", c.escape_default());
+                        }
+                        c => text.push(c),
+                    }
+                }
+            }
+            Segment::Redacted { length, .. } => {
+                let _ = write!(
+                    text,
+                    "{ANSI_REDACTED}{}{ANSI_RESET}",
+                    marker.render(*length)
+                );
+            }
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_from_authenticated_ranges() {
+        // The NUL byte at 2 is revealed, the bytes at 4..7 are redacted.
+        let data = b"ab\0cdefg";
+        let revealed = Revealed::new(data, [0..4, 7..8]);
+        let segments = segments(&revealed);
+
+        assert_eq!(
+            segments,
+            vec![
+                Segment::Revealed {
+                    offset: 0,
+                    length: 4,
+                    data: b"ab\0c".to_vec(),
+                },
+                Segment::Redacted {
+                    offset: 4,
+                    length: 3,
+                },
+                Segment::Revealed {
+                    offset: 7,
+                    length: 1,
+                    data: b"g".to_vec(),
+                },
+            ]
+        );
+        assert_eq!(Rendering::default().render(&segments), "ab\0c🙈🙈🙈g");
+        assert_eq!(
+            Rendering::Text(Marker::PerSpan("[{len} redacted]".to_string())).render(&segments),
+            "ab\0c[3 redacted]g"
+        );
+        assert_eq!(
+            Rendering::Ansi(Marker::PerByte("*".to_string())).render(&segments),
+            "ab\x1b[33m\\u{0}\x1b[0mc\x1b[7;2m***\x1b[0mg"
+        );
+        assert_eq!(
+            Rendering::Json(Encoding::Utf8Lossy).render(&segments[1..]),
+            r#"[{"kind":"redacted","length":3,"offset":4},{"data":"g","kind":"revealed","length":1,"offset":7}]"#
+        );
+    }
+
+    #[test]
+    fn renders_binary_data() {
+        let data = [0x1f, 0x8b, 0xff, 0x00, 0x08];
+        let segments = segments(&Revealed::new(&data, [0..3, 4..5]));
+
+        assert_eq!(
+            Rendering::Hex(Marker::PerByte("__".to_string())).render(&segments),
+            "1f8bff__08"
+        );
+        assert_eq!(
+            Rendering::Json(Encoding::Base64).render(&segments[..1]),
+            r#"[{"data":"H4v/","kind":"revealed","length":3,"offset":0}]"#
+        );
+        assert_eq!(
+            Rendering::default().render(&segments),
+            "\x1f\u{fffd}\u{fffd}🙈\x08"
+        );
+    }
+}
@@ -0,0 +1,398 @@
+//! Detection of sessions proving the same data again.
+//!
+//! A [`ReplayGuard`] hashes selected revealed fields of every session it checks, e.g. an account
+//! id and a date, and flags or rejects sessions whose fields match those of an earlier session
+//! within a time window. Only the hashes are kept.
+use async_trait::async_trait;
+use eyre::eyre;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+use tlsn_core::VerifierOutput;
+
+use crate::{
+    http::Exchange,
+    json::Lookup,
+    policy::{exchanges, TranscriptPolicy, Verdict},
+};
+
+/// A revealed field identifying what a session proves. Exchanges are counted from 0.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ReplayField {
+    ServerName,
+    RequestTarget {
+        exchange: usize,
+    },
+    RequestHeader {
+        exchange: usize,
+        name: String,
+    },
+    ResponseHeader {
+        exchange: usize,
+        name: String,
+    },
+    /// A value in the JSON body of a response, looked up by a dot-separated path.
+    ResponseJson {
+        exchange: usize,
+        path: String,
+    },
+}
+
+impl fmt::Display for ReplayField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayField::ServerName => write!(f, "server name"),
+            ReplayField::RequestTarget { exchange } => write!(f, "request {exchange} target"),
+            ReplayField::RequestHeader { exchange, name } => {
+                write!(f, "request {exchange} header {name}")
+            }
+            ReplayField::ResponseHeader { exchange, name } => {
+                write!(f, "response {exchange} header {name}")
+            }
+            ReplayField::ResponseJson { exchange, path } => {
+                write!(f, "response {exchange} json {path}")
+            }
+        }
+    }
+}
+
+/// Rejects, or only flags, sessions revealing the same fields as an earlier session checked by
+/// this guard within a time window.
+///
+/// A session reserves its fields when checked, so of two concurrent sessions with the same fields
+/// only one passes. The reservation is kept once the overall verdict passes and dropped once it
+/// fails, so a session failing another policy does not make a later, valid one a duplicate. Share
+/// one guard across sessions, e.g. by returning the same policy from a
+/// [`PolicySource`](crate::PolicySource).
+pub struct ReplayGuard {
+    fields: Vec<ReplayField>,
+    window: Duration,
+    reject: bool,
+    seen: Mutex<HashMap<[u8; 32], Seen>>,
+}
+
+/// A session recorded by a [`ReplayGuard`].
+struct Seen {
+    at: Instant,
+    /// The session holding the entry until its overall verdict is known.
+    pending: Option<SessionId>,
+}
+
+/// Identifies a session while it is verified.
+type SessionId = usize;
+
+/// The same output is passed to `check` and then to `commit` or `rollback`, and the outputs of
+/// concurrent sessions are distinct, so its address identifies the session meanwhile.
+fn session_id(output: &VerifierOutput) -> SessionId {
+    output as *const VerifierOutput as usize
+}
+
+impl ReplayGuard {
+    /// Rejects sessions whose `fields` match those of a session checked within `window`. All the
+    /// fields must be revealed.
+    pub fn new(window: Duration, fields: impl IntoIterator<Item = ReplayField>) -> Self {
+        Self {
+            fields: fields.into_iter().collect(),
+            window,
+            reject: true,
+            seen: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Lets duplicates pass, only flagging them in the verdict.
+    pub fn flag_only(mut self) -> Self {
+        self.reject = false;
+        self
+    }
+
+    fn key(&self, output: &VerifierOutput) -> Result<[u8; 32], eyre::ErrReport> {
+        let server_name = output.server_name.as_ref().map(|name| name.as_str());
+        self.key_of(server_name, || exchanges(output))
+    }
+
+    /// Hashes the fields of a session with `server_name`, parsing its exchanges only if needed.
+    fn key_of(
+        &self,
+        server_name: Option<&str>,
+        exchanges: impl FnOnce() -> Result<Vec<Exchange>, eyre::ErrReport>,
+    ) -> Result<[u8; 32], eyre::ErrReport> {
+        let needs_exchanges = self
+            .fields
+            .iter()
+            .any(|field| *field != ReplayField::ServerName);
+        let exchanges = if needs_exchanges {
+            exchanges()?
+        } else {
+            Vec::new()
+        };
+
+        let mut hasher = Sha256::new();
+        for field in &self.fields {
+            let value = field_value(field, server_name, &exchanges)
+                .map_err(|err| eyre!("{field} cannot be used: {err}"))?;
+            // Length-prefix values so field boundaries cannot be shifted.
+            hasher.update((value.len() as u64).to_be_bytes());
+            hasher.update(&value);
+        }
+        Ok(hasher.finalize().into())
+    }
+
+    fn seen(&self, now: Instant) -> MutexGuard<'_, HashMap<[u8; 32], Seen>> {
+        let mut seen = self.seen.lock().unwrap_or_else(|err| err.into_inner());
+        // Reservations of sessions never finished expire as well.
+        seen.retain(|_, entry| now.duration_since(entry.at) < self.window);
+        seen
+    }
+
+    /// Checks the session `session` with `key` at `now`, reserving the key if no session within
+    /// the window has it.
+    fn check_key(
+        &self,
+        key: Result<[u8; 32], eyre::ErrReport>,
+        session: SessionId,
+        now: Instant,
+    ) -> Verdict {
+        let fields = self
+            .fields
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        let policy = format!(
+            "{} not seen within {}s",
+            fields.join(", "),
+            self.window.as_secs()
+        );
+        let key = match key {
+            Ok(key) => key,
+            Err(err) => return Verdict::fail(policy, err.to_string()),
+        };
+
+        let mut seen = self.seen(now);
+        let reason = match seen.get(&key) {
+            Some(Seen {
+                pending: Some(_), ..
+            }) => "same as a session being verified".to_string(),
+            Some(Seen { at, .. }) => {
+                format!(
+                    "same as a session {}s ago",
+                    now.duration_since(*at).as_secs()
+                )
+            }
+            None => {
+                seen.insert(
+                    key,
+                    Seen {
+                        at: now,
+                        pending: Some(session),
+                    },
+                );
+                return Verdict::pass(policy);
+            }
+        };
+        let verdict = if self.reject {
+            Verdict::fail(policy, reason)
+        } else {
+            Verdict::pass(policy).with_reason(reason)
+        };
+        verdict.flag_duplicate()
+    }
+
+    /// Keeps the key of `session` once its overall verdict passed.
+    fn commit_key(&self, key: [u8; 32], session: SessionId, now: Instant) {
+        let mut seen = self.seen(now);
+        let entry = seen.entry(key).or_insert(Seen {
+            // A flagged duplicate outlived the session it duplicated.
+            at: now,
+            pending: None,
+        });
+        if entry.pending == Some(session) {
+            entry.pending = None;
+        }
+    }
+
+    /// Drops the key reserved by `session` once its overall verdict failed.
+    fn rollback_key(&self, key: [u8; 32], session: SessionId) {
+        let mut seen = self.seen.lock().unwrap_or_else(|err| err.into_inner());
+        if seen
+            .get(&key)
+            .is_some_and(|entry| entry.pending == Some(session))
+        {
+            seen.remove(&key);
+        }
+    }
+}
+
+#[async_trait]
+impl TranscriptPolicy for ReplayGuard {
+    async fn check(&self, output: &VerifierOutput) -> Verdict {
+        self.check_key(self.key(output), session_id(output), Instant::now())
+    }
+
+    fn commit(&self, output: &VerifierOutput) {
+        // Sessions whose fields cannot be read, e.g. under `any`, have nothing to record.
+        if let Ok(key) = self.key(output) {
+            self.commit_key(key, session_id(output), Instant::now());
+        }
+    }
+
+    fn rollback(&self, output: &VerifierOutput) {
+        if let Ok(key) = self.key(output) {
+            self.rollback_key(key, session_id(output));
+        }
+    }
+}
+
+fn field_value(
+    field: &ReplayField,
+    server_name: Option<&str>,
+    exchanges: &[Exchange],
+) -> Result<Vec<u8>, eyre::ErrReport> {
+    let exchange = |idx: usize| {
+        exchanges
+            .get(idx)
+            .ok_or_else(|| eyre!("session has no exchange {idx}"))
+    };
+    let revealed = |value: Option<Vec<u8>>| value.ok_or_else(|| eyre!("it is redacted"));
+
+    match field {
+        ReplayField::ServerName => server_name
+            .map(|name| name.as_bytes().to_vec())
+            .ok_or_else(|| eyre!("it was not revealed")),
+        ReplayField::RequestTarget { exchange: idx } => revealed(
+            exchange(*idx)?
+                .request
+                .target
+                .clone()
+                .map(String::into_bytes),
+        ),
+        ReplayField::RequestHeader {
+            exchange: idx,
+            name,
+        } => {
+            let header = exchange(*idx)?
+                .request
+                .header(name)
+                .ok_or_else(|| eyre!("it is missing"))?;
+            revealed(header.value.clone())
+        }
+        ReplayField::ResponseHeader {
+            exchange: idx,
+            name,
+        } => {
+            let header = exchange(*idx)?
+                .response
+                .header(name)
+                .ok_or_else(|| eyre!("it is missing"))?;
+            revealed(header.value.clone())
+        }
+        ReplayField::ResponseJson {
+            exchange: idx,
+            path,
+        } => {
+            let body = exchange(*idx)?
+                .response
+                .body
+                .as_ref()
+                .ok_or_else(|| eyre!("response has no body"))?;
+            let json = body.json()?;
+            match json.lookup(path) {
+                Lookup::Found(value) => {
+                    revealed(value.to_value().map(|value| value.to_string().into_bytes()))
+                }
+                Lookup::Redacted(_) => Err(eyre!("it is redacted")),
+                Lookup::Missing => Err(eyre!("it is missing")),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{http::parse_exchanges, transcript::Revealed};
+
+    fn guard() -> ReplayGuard {
+        ReplayGuard::new(
+            Duration::from_secs(60),
+            [
+                ReplayField::ServerName,
+                ReplayField::ResponseJson {
+                    exchange: 0,
+                    path: "account".into(),
+                },
+            ],
+        )
+    }
+
+    /// The key of a session fetching `account` from example.com.
+    fn key(guard: &ReplayGuard, account: &str) -> Result<[u8; 32], eyre::ErrReport> {
+        let sent = "GET /account HTTP/1.1\r\n\r\n";
+        let body = format!("{{\"account\": \"{account}\"}}");
+        let received = format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        );
+        guard.key_of(Some("example.com"), || {
+            parse_exchanges(
+                &Revealed::new(sent.as_bytes(), std::iter::once(0..sent.len())),
+                &Revealed::new(received.as_bytes(), std::iter::once(0..received.len())),
+            )
+        })
+    }
+
+    #[test]
+    fn detects_duplicates_within_window() {
+        let guard = guard();
+        let start = Instant::now();
+
+        assert!(guard.check_key(key(&guard, "a"), 1, start).passed);
+        guard.commit_key(key(&guard, "a").unwrap(), 1, start);
+        assert!(guard.check_key(key(&guard, "b"), 2, start).passed);
+
+        let verdict = guard.check_key(key(&guard, "a"), 3, start + Duration::from_secs(10));
+        assert!(!verdict.passed);
+        assert!(verdict.duplicate);
+        assert_eq!(verdict.reason.as_deref(), Some("same as a session 10s ago"));
+        // The first session has left the window.
+        assert!(
+            guard
+                .check_key(key(&guard, "a"), 4, start + Duration::from_secs(61))
+                .passed
+        );
+        assert!(guard
+            .check_key(Err(eyre!("redacted")), 5, start)
+            .reason
+            .is_some());
+    }
+
+    #[test]
+    fn reserves_sessions_until_their_verdict() {
+        let guard = guard();
+        let now = Instant::now();
+
+        // A session whose sibling policy failed is rolled back, and does not block a later one.
+        assert!(guard.check_key(key(&guard, "a"), 1, now).passed);
+        guard.rollback_key(key(&guard, "a").unwrap(), 1);
+        assert!(guard.check_key(key(&guard, "a"), 2, now).passed);
+
+        // A concurrent session is checked before the first one commits.
+        let verdict = guard.check_key(key(&guard, "a"), 3, now);
+        assert!(!verdict.passed);
+        assert_eq!(
+            verdict.reason.as_deref(),
+            Some("same as a session being verified")
+        );
+        // Rolling back the rejected session keeps the reservation of the first one.
+        guard.rollback_key(key(&guard, "a").unwrap(), 3);
+        guard.commit_key(key(&guard, "a").unwrap(), 2, now);
+        let verdict = guard.check_key(key(&guard, "a"), 4, now);
+        assert_eq!(verdict.reason.as_deref(), Some("same as a session 0s ago"));
+        // A committed session is not rolled back by a later one reusing its id.
+        guard.rollback_key(key(&guard, "a").unwrap(), 2);
+        assert!(!guard.check_key(key(&guard, "a"), 5, now).passed);
+    }
+}
@@ -0,0 +1,471 @@
+//! Verification rules written as [Rhai](https://rhai.rs) scripts.
+//!
+//! Scripts run sandboxed on a copy of the session, so they cannot affect the verification or
+//! other policies, and must evaluate to `true` to pass. They can `throw` a message to fail with
+//! a reason. The following constants are in scope:
+//!
+//! - `server_name`: the revealed server name, or `()`.
+//! - `connection_time`: the Unix time in seconds the TLS connection was established.
+//! - `request`: `#{ method, target, version, headers, body, bytes, json }` of the sent request.
+//! - `response`: `#{ version, status, reason, headers, body, bytes, json }` of the received
+//!   response.
+//! - `exchanges`: `[#{ request, response }]`, every exchange of the session in order. `request`
+//!   and `response` above are those of the first exchange.
+//! - `redactions`: `#{ sent, received }`, the redacted `[start, end]` ranges of each direction.
+//!
+//! `body` is lossily decoded as UTF-8, `bytes` holds the raw body as a blob for binary payloads.
+//! Header names are lowercased. Redacted values have the type `"redacted"`, so
+//! `type_of(response.json.name) == "redacted"` tells a redacted value from a missing one.
+//! Objects whose redacted parts may hide members besides those listed also hold a redacted
+//! `"..."` entry, so a missing member is only known to be absent if `!("..." in object)`.
+use async_trait::async_trait;
+use eyre::eyre;
+use rhai::{
+    module_resolvers::DummyModuleResolver, Array, Dynamic, Engine, Map, Position, Scope, AST,
+};
+use std::{
+    ops::Range,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tlsn_core::VerifierOutput;
+
+use crate::{
+    http::{self, Body, Header, Request, Response},
+    json::{self, Json, JsonKind},
+    policy::{TranscriptPolicy, Verdict},
+    service::PolicySource,
+    transcript::Revealed,
+};
+
+/// Limits on the resources a script may use.
+#[derive(Clone, Copy, Debug)]
+pub struct ScriptLimits {
+    /// Maximum number of operations, roughly the number of evaluated expressions.
+    pub max_operations: u64,
+    /// Maximum wall-clock time of an evaluation.
+    pub max_duration: Duration,
+}
+
+impl Default for ScriptLimits {
+    fn default() -> Self {
+        Self {
+            max_operations: 100_000,
+            max_duration: Duration::from_millis(500),
+        }
+    }
+}
+
+/// Key of the entry marking JSON objects whose redacted parts may hide further members.
+const HIDDEN_MEMBERS: &str = "...";
+
+/// Marker for a redacted value in scripts.
+#[derive(Clone, Copy, Debug)]
+struct Redacted;
+
+/// A policy evaluating a Rhai script.
+#[derive(Clone)]
+pub struct ScriptPolicy {
+    name: String,
+    ast: Arc<AST>,
+    limits: ScriptLimits,
+}
+
+impl ScriptPolicy {
+    /// Compiles `source`. Syntax errors are reported with their line number.
+    pub fn new(
+        name: impl Into<String>,
+        source: &str,
+        limits: ScriptLimits,
+    ) -> Result<Self, eyre::ErrReport> {
+        let name = name.into();
+        let ast = engine(&limits)
+            .compile(source)
+            .map_err(|err| eyre!("{name}: {}", describe(&err.0.to_string(), err.1)))?;
+
+        Ok(Self {
+            name,
+            ast: Arc::new(ast),
+            limits,
+        })
+    }
+
+    fn eval(&self, scope: &mut Scope<'_>) -> Result<Dynamic, String> {
+        let started = Instant::now();
+        let max_duration = self.limits.max_duration;
+        let mut engine = engine(&self.limits);
+        engine.on_progress(move |_| {
+            (started.elapsed() > max_duration).then(|| "script timed out".into())
+        });
+
+        engine
+            .eval_ast_with_scope::<Dynamic>(scope, &self.ast)
+            .map_err(|err| {
+                let position = err.position();
+                let message = match *err {
+                    rhai::EvalAltResult::ErrorRuntime(value, _) => value.to_string(),
+                    rhai::EvalAltResult::ErrorTerminated(reason, _) => reason.to_string(),
+                    err => err.unwrap_inner().to_string(),
+                };
+                describe(&message, position)
+            })
+    }
+}
+
+#[async_trait]
+impl TranscriptPolicy for ScriptPolicy {
+    async fn check(&self, output: &VerifierOutput) -> Verdict {
+        let policy = format!("script {}", self.name);
+        let mut scope = match scope(output) {
+            Ok(scope) => scope,
+            Err(err) => return Verdict::fail(policy, err.to_string()),
+        };
+
+        let script = self.clone();
+        let result = tokio::task::spawn_blocking(move || script.eval(&mut scope)).await;
+
+        match result {
+            Ok(Ok(value)) => match value.as_bool() {
+                Ok(true) => Verdict::pass(policy),
+                Ok(false) => Verdict::fail(policy, "script returned false"),
+                Err(ty) => Verdict::fail(policy, format!("script returned a {ty}, not a bool")),
+            },
+            Ok(Err(err)) => Verdict::fail(policy, err),
+            Err(err) => Verdict::fail(policy, format!("script panicked: {err}")),
+        }
+    }
+}
+
+/// A script file, re-read for every session so rules can change without a restart. A script
+/// that does not compile fails sessions with the compile error, line number included.
+pub struct ScriptFile {
+    pub path: PathBuf,
+    pub limits: ScriptLimits,
+}
+
+#[async_trait]
+impl PolicySource for ScriptFile {
+    async fn policy(&self) -> Result<Arc<dyn TranscriptPolicy>, eyre::ErrReport> {
+        let source = tokio::fs::read_to_string(&self.path)
+            .await
+            .map_err(|err| eyre!("Failed to read {}: {err}", self.path.display()))?;
+        let name = self.path.display().to_string();
+        Ok(match compile(&name, &source, self.limits) {
+            Ok(policy) => policy.boxed(),
+            Err(uncompiled) => uncompiled.boxed(),
+        })
+    }
+}
+
+fn compile(name: &str, source: &str, limits: ScriptLimits) -> Result<ScriptPolicy, Uncompiled> {
+    ScriptPolicy::new(name, source, limits).map_err(|err| Uncompiled {
+        policy: format!("script {name}"),
+        error: err.to_string(),
+    })
+}
+
+/// A script that failed to compile, failing every session with the compile error.
+struct Uncompiled {
+    policy: String,
+    error: String,
+}
+
+#[async_trait]
+impl TranscriptPolicy for Uncompiled {
+    async fn check(&self, _output: &VerifierOutput) -> Verdict {
+        Verdict::fail(&self.policy, &self.error)
+    }
+}
+
+fn engine(limits: &ScriptLimits) -> Engine {
+    let mut engine = Engine::new();
+    // The default resolver loads modules from files, which would let scripts read the disk.
+    engine
+        .set_module_resolver(DummyModuleResolver::new())
+        .set_max_operations(limits.max_operations)
+        .set_max_call_levels(32)
+        .set_max_expr_depths(64, 32)
+        .set_max_string_size(1 << 20)
+        .set_max_array_size(10_000)
+        .set_max_map_size(10_000)
+        .disable_symbol("eval")
+        .register_type_with_name::<Redacted>("redacted");
+    engine
+}
+
+fn describe(message: &str, position: Position) -> String {
+    match position.line() {
+        Some(line) => format!("line {line}: {message}"),
+        None => message.to_string(),
+    }
+}
+
+/// Builds the read-only scope of a script from the session.
+fn scope(output: &VerifierOutput) -> Result<Scope<'static>, eyre::ErrReport> {
+    let transcript = output
+        .transcript
+        .as_ref()
+        .ok_or_else(|| eyre!("no transcript data was revealed"))?;
+    let sent = Revealed::sent(transcript);
+    let received = Revealed::received(transcript);
+
+    let exchanges = http::parse_exchanges(&sent, &received)?;
+    let first = exchanges
+        .first()
+        .ok_or_else(|| eyre!("no request was revealed"))?;
+    let request = request_map(&first.request);
+    let response = response_map(&first.response);
+    let exchanges = exchanges
+        .iter()
+        .map(|exchange| {
+            let mut map = Map::new();
+            map.insert("request".into(), request_map(&exchange.request).into());
+            map.insert("response".into(), response_map(&exchange.response).into());
+            Dynamic::from_map(map)
+        })
+        .collect::<Array>();
+
+    let mut redactions = Map::new();
+    redactions.insert("sent".into(), ranges(sent.redacted()));
+    redactions.insert("received".into(), ranges(received.redacted()));
+
+    let mut scope = Scope::new();
+    scope.push_constant(
+        "server_name",
+        output
+            .server_name
+            .as_ref()
+            .map(|name| Dynamic::from(name.as_str().to_string()))
+            .unwrap_or(Dynamic::UNIT),
+    );
+    scope.push_constant(
+        "connection_time",
+        Dynamic::from_int(output.connection_info.time as rhai::INT),
+    );
+    scope.push_constant("request", request);
+    scope.push_constant("response", response);
+    scope.push_constant("exchanges", exchanges);
+    scope.push_constant("redactions", redactions);
+    Ok(scope)
+}
+
+fn request_map(request: &Request) -> Map {
+    let mut map = Map::new();
+    map.insert("method".into(), optional(request.method.clone()));
+    map.insert("target".into(), optional(request.target.clone()));
+    map.insert("version".into(), optional(request.version.clone()));
+    map.insert("headers".into(), headers(&request.headers));
+    insert_body(&mut map, request.body.as_ref());
+    map
+}
+
+fn response_map(response: &Response) -> Map {
+    let mut map = Map::new();
+    map.insert("version".into(), optional(response.version.clone()));
+    map.insert(
+        "status".into(),
+        response
+            .status
+            .map(|status| Dynamic::from_int(status.into()))
+            .unwrap_or_else(|| Dynamic::from(Redacted)),
+    );
+    map.insert("reason".into(), optional(response.reason.clone()));
+    map.insert("headers".into(), headers(&response.headers));
+    insert_body(&mut map, response.body.as_ref());
+    map
+}
+
+fn optional(value: Option<String>) -> Dynamic {
+    value
+        .map(Dynamic::from)
+        .unwrap_or_else(|| Dynamic::from(Redacted))
+}
+
+fn headers(headers: &[Header]) -> Dynamic {
+    let mut map = Map::new();
+    for header in headers {
+        let value = match &header.value {
+            Some(value) => Dynamic::from(String::from_utf8_lossy(value).into_owned()),
+            None => Dynamic::from(Redacted),
+        };
+        map.insert(header.name.as_str().into(), value);
+    }
+    map.into()
+}
+
+/// Inserts `body` (the body as a string), `bytes` (the body as a blob) and `json` (the parsed
+/// body), any of which is `()` if missing or unparseable.
+fn insert_body(map: &mut Map, body: Option<&Body>) {
+    let (text, bytes) = match body {
+        Some(body) if body.is_fully_revealed() => (
+            Dynamic::from(String::from_utf8_lossy(&body.data).into_owned()),
+            Dynamic::from_blob(body.data.clone()),
+        ),
+        Some(_) => (Dynamic::from(Redacted), Dynamic::from(Redacted)),
+        None => (Dynamic::UNIT, Dynamic::UNIT),
+    };
+    let json = body
+        .and_then(|body| body.json().ok())
+        .and_then(|json| json_to_dynamic(&json, 0))
+        .unwrap_or(Dynamic::UNIT);
+
+    map.insert("body".into(), text);
+    map.insert("bytes".into(), bytes);
+    map.insert("json".into(), json);
+}
+
+/// Converts `json` nested in `depth` arrays and objects, or `None` if it exceeds
+/// [`json::MAX_DEPTH`].
+fn json_to_dynamic(json: &Json, depth: usize) -> Option<Dynamic> {
+    Some(match &json.kind {
+        JsonKind::Null => Dynamic::UNIT,
+        JsonKind::Bool(value) => Dynamic::from_bool(*value),
+        JsonKind::Number(literal) => match literal.parse::<rhai::INT>() {
+            Ok(value) => Dynamic::from_int(value),
+            Err(_) => literal
+                .parse::<rhai::FLOAT>()
+                .map(Dynamic::from_float)
+                .unwrap_or_else(|_| Dynamic::from(literal.clone())),
+        },
+        JsonKind::String(value) => Dynamic::from(value.clone()),
+        JsonKind::Array(_) | JsonKind::Object(_) if depth == json::MAX_DEPTH => return None,
+        JsonKind::Array(elems) => elems
+            .iter()
+            .map(|elem| json_to_dynamic(elem, depth + 1))
+            .collect::<Option<Array>>()?
+            .into(),
+        JsonKind::Object(members) => {
+            let mut map = Map::new();
+            for member in members {
+                if let Some(key) = member.key.as_deref() {
+                    map.insert(key.into(), json_to_dynamic(&member.value, depth + 1)?);
+                }
+            }
+            if json.may_hide_members() {
+                map.insert(HIDDEN_MEMBERS.into(), Dynamic::from(Redacted));
+            }
+            map.into()
+        }
+        JsonKind::Redacted => Dynamic::from(Redacted),
+    })
+}
+
+fn ranges(ranges: Vec<Range<usize>>) -> Dynamic {
+    ranges
+        .into_iter()
+        .map(|range| {
+            vec![
+                Dynamic::from_int(range.start as rhai::INT),
+                Dynamic::from_int(range.end as rhai::INT),
+            ]
+            .into()
+        })
+        .collect::<Array>()
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_errors_with_line_numbers() {
+        let err = ScriptPolicy::new("broken", "let x = 1;\nlet = 2;", ScriptLimits::default())
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("line 2"), "{err}");
+
+        let policy = ScriptPolicy::new(
+            "throws",
+            "let x = 1;\n\nthrow \"balance too low\";",
+            ScriptLimits::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            policy.eval(&mut Scope::new()).unwrap_err(),
+            "line 3: balance too low"
+        );
+    }
+
+    #[test]
+    fn marks_objects_that_may_hide_members() {
+        let input = r#"{"a": ***********, "c": {"d": 1}}"#;
+        let revealed = input.bytes().map(|byte| byte != b'*').collect::<Vec<_>>();
+        let json = Json::parse(input.as_bytes(), &revealed).unwrap();
+        let mut scope = Scope::new();
+        scope.push_constant("json", json_to_dynamic(&json, 0).unwrap());
+
+        let policy = ScriptPolicy::new(
+            "hidden",
+            r#"type_of(json.b) == "()" && "..." in json && !("..." in json.c)"#,
+            ScriptLimits::default(),
+        )
+        .unwrap();
+        assert_eq!(policy.eval(&mut scope).unwrap().as_bool(), Ok(true));
+    }
+
+    #[test]
+    fn limits_json_nesting_depth() {
+        let nested = Json {
+            kind: JsonKind::Null,
+            span: 0..0,
+        };
+        let nested = (0..=json::MAX_DEPTH).fold(nested, |json, _| Json {
+            kind: JsonKind::Array(vec![json]),
+            span: 0..0,
+        });
+
+        assert!(json_to_dynamic(&nested, 0).is_none());
+        let JsonKind::Array(elems) = &nested.kind else {
+            unreachable!()
+        };
+        assert!(json_to_dynamic(&elems[0], 0).is_some());
+    }
+
+    #[tokio::test]
+    async fn loads_scripts_failing_to_compile() {
+        let path = std::env::temp_dir().join("tlsn-verifier-script-broken.rhai");
+        std::fs::write(&path, "let x = 1;\nlet = 2;").unwrap();
+        let file = ScriptFile {
+            path: path.clone(),
+            limits: ScriptLimits::default(),
+        };
+
+        let policy = file.policy().await;
+        std::fs::remove_file(&path).unwrap();
+        assert!(policy.is_ok());
+
+        let Err(uncompiled) = compile("broken", "let x = 1;\nlet = 2;", ScriptLimits::default())
+        else {
+            panic!("script should not compile");
+        };
+        assert!(uncompiled.error.contains("line 2"), "{}", uncompiled.error);
+    }
+
+    #[test]
+    fn cannot_import_modules() {
+        let path = std::env::temp_dir().join("tlsn-verifier-script-module.rhai");
+        std::fs::write(&path, "export const SECRET = 42;").unwrap();
+        let source = format!(
+            "import {:?} as m; m::SECRET == 42",
+            path.with_extension("").display().to_string()
+        );
+
+        let policy = ScriptPolicy::new("import", &source, ScriptLimits::default()).unwrap();
+        let err = policy.eval(&mut Scope::new()).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert!(err.contains("not found"), "{err}");
+    }
+
+    #[test]
+    fn enforces_operation_limit() {
+        let limits = ScriptLimits {
+            max_operations: 1_000,
+            ..Default::default()
+        };
+        let policy = ScriptPolicy::new("loop", "loop {}", limits).unwrap();
+
+        assert!(policy.eval(&mut Scope::new()).is_err());
+    }
+}
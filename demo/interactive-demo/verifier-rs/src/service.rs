@@ -0,0 +1,454 @@
+//! Embeddable verifier service.
+//!
+//! [`VerifierService`] bundles the policy, limits and outcome handler of a verifier, and
+//! turns them into an axum [`Router`] serving `/verify` that can be nested into other
+//! applications. [`run_server`](crate::run_server) is a thin wrapper over it.
+use async_trait::async_trait;
+use axum::{
+    extract::{FromRequestParts, Request, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use futures_util::future::BoxFuture;
+use http::{header, HeaderValue};
+use serde::Serialize;
+use std::{
+    fmt,
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::SystemTime,
+};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tracing::{debug, error, info, warn};
+use ws_stream_tungstenite::WsStream;
+
+use crate::{
+    axum_websocket::{header_eq, WebSocket, WebSocketUpgrade},
+    policy::{all, Nonce, NonceLocation, TranscriptPolicy, Verdict},
+    render::{Rendering, Segment},
+    tcp::TcpUpgrade,
+    verifier,
+};
+
+// Maximum number of bytes that can be sent from prover to server
+const MAX_SENT_DATA: usize = 1 << 12;
+// Maximum number of bytes that can be received by prover from server
+const MAX_RECV_DATA: usize = 1 << 14;
+
+/// WebSocket subprotocols accepted on `/verify`, in decreasing order of preference.
+///
+/// Provers offer `tlsn/<version>` for the tlsn release they were built with, so that a
+/// version mismatch is refused at upgrade time instead of failing inside MPC.
+pub const SUPPORTED_PROTOCOLS: &[&str] = &["tlsn/0.1.0-alpha.12"];
+
+/// Header of the upgrade response carrying the nonce issued for the session, see
+/// [`VerifierServiceBuilder::nonce`].
+pub const NONCE_HEADER: &str = "x-tlsn-nonce";
+
+/// Provides the policy for each verification session, e.g. loaded from a database.
+///
+/// The policy is fetched before the connection is upgraded, so a failure here refuses the
+/// prover instead of wasting an MPC session.
+#[async_trait]
+pub trait PolicySource: Send + Sync + 'static {
+    async fn policy(&self) -> Result<Arc<dyn TranscriptPolicy>, eyre::ErrReport>;
+}
+
+#[async_trait]
+impl PolicySource for Arc<dyn TranscriptPolicy> {
+    async fn policy(&self) -> Result<Arc<dyn TranscriptPolicy>, eyre::ErrReport> {
+        Ok(self.clone())
+    }
+}
+
+/// Limits on the amount of data a prover may exchange with the server.
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    /// Maximum number of bytes that can be sent from prover to server.
+    pub max_sent_data: usize,
+    /// Maximum number of bytes that can be received by prover from server.
+    pub max_recv_data: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_sent_data: MAX_SENT_DATA,
+            max_recv_data: MAX_RECV_DATA,
+        }
+    }
+}
+
+/// Transport a prover connected with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Transport {
+    /// `Upgrade: websocket`, used by browser provers.
+    WebSocket,
+    /// `Upgrade: tlsn`, a raw TCP stream used by native provers.
+    Tcp,
+}
+
+impl fmt::Display for Transport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Transport::WebSocket => write!(f, "websocket"),
+            Transport::Tcp => write!(f, "tcp"),
+        }
+    }
+}
+
+/// Data revealed by the prover, with the verdict of the policy on it.
+#[derive(Clone, Debug)]
+pub struct VerifiedData {
+    /// Name of the server the prover connected to, if revealed.
+    pub server_name: Option<String>,
+    /// Time the TLS connection to the server was established.
+    pub connection_time: SystemTime,
+    /// Whether a policy found the session to duplicate an earlier one, see
+    /// [`ReplayGuard`](crate::replay::ReplayGuard).
+    pub duplicate: bool,
+    pub verdict: Verdict,
+    /// Sent data, rendered as configured with [`VerifierServiceBuilder::rendering`].
+    pub sent: String,
+    /// Received data, rendered as configured with [`VerifierServiceBuilder::rendering`].
+    pub received: String,
+    pub sent_segments: Vec<Segment>,
+    pub received_segments: Vec<Segment>,
+}
+
+/// Outcome of a verification session, passed to the outcome handler.
+#[derive(Debug)]
+pub struct VerificationOutcome {
+    pub transport: Transport,
+    /// The revealed data, or why the MPC-TLS session failed.
+    pub result: Result<VerifiedData, eyre::ErrReport>,
+}
+
+impl VerificationOutcome {
+    /// Whether the session completed and the policy passed.
+    pub fn is_verified(&self) -> bool {
+        matches!(&self.result, Ok(data) if data.verdict.passed)
+    }
+}
+
+/// Counts of the sessions handled by a [`VerifierService`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Metrics {
+    /// Sessions whose data passed the policy.
+    pub verified: u64,
+    /// Sessions whose data failed the policy.
+    pub rejected: u64,
+    /// Sessions that failed before the policy was applied, e.g. in MPC-TLS.
+    pub failed: u64,
+    /// Sessions flagged as duplicates, whether rejected or not.
+    pub duplicates: u64,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    verified: AtomicU64,
+    rejected: AtomicU64,
+    failed: AtomicU64,
+    duplicates: AtomicU64,
+}
+
+impl Counters {
+    fn record(&self, result: &Result<VerifiedData, eyre::ErrReport>) {
+        let counter = match result {
+            Ok(data) if data.verdict.passed => &self.verified,
+            Ok(_) => &self.rejected,
+            Err(_) => &self.failed,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        if matches!(result, Ok(data) if data.duplicate) {
+            self.duplicates.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+type OutcomeHandler = Arc<dyn Fn(VerificationOutcome) -> BoxFuture<'static, ()> + Send + Sync>;
+
+/// Verifier service that can be served on its own or nested into another axum application.
+///
+/// ```ignore
+/// let service = VerifierService::builder(ServerName::new("example.com").boxed())
+///     .on_outcome(|outcome| async move { store(outcome).await })
+///     .build();
+///
+/// let app = Router::new().nest("/tlsn", service.into_router());
+/// ```
+#[derive(Clone)]
+pub struct VerifierService {
+    policy_source: Arc<dyn PolicySource>,
+    limits: Limits,
+    rendering: Rendering,
+    nonce: Option<NonceLocation>,
+    on_outcome: OutcomeHandler,
+    counters: Arc<Counters>,
+}
+
+impl fmt::Debug for VerifierService {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VerifierService")
+            .field("limits", &self.limits)
+            .field("nonce", &self.nonce)
+            .finish_non_exhaustive()
+    }
+}
+
+impl VerifierService {
+    /// Creates a builder for a service applying the policies from `policy_source`.
+    pub fn builder(policy_source: impl PolicySource) -> VerifierServiceBuilder {
+        VerifierServiceBuilder {
+            policy_source: Arc::new(policy_source),
+            limits: Limits::default(),
+            rendering: Rendering::default(),
+            nonce: None,
+            on_outcome: Arc::new(|outcome| Box::pin(log_outcome(outcome))),
+        }
+    }
+
+    /// Returns the counts of the sessions handled so far.
+    pub fn metrics(&self) -> Metrics {
+        Metrics {
+            verified: self.counters.verified.load(Ordering::Relaxed),
+            rejected: self.counters.rejected.load(Ordering::Relaxed),
+            failed: self.counters.failed.load(Ordering::Relaxed),
+            duplicates: self.counters.duplicates.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Returns a router serving `/verify`, for any outer router state.
+    pub fn into_router<S>(self) -> Router<S>
+    where
+        S: Clone + Send + Sync + 'static,
+    {
+        Router::new()
+            .route("/verify", get(upgrade_protocol))
+            .with_state(self)
+    }
+
+    async fn handle_verification<T: AsyncWrite + AsyncRead + Send + Unpin + 'static>(
+        &self,
+        stream: T,
+        policy: Arc<dyn TranscriptPolicy>,
+        transport: Transport,
+    ) {
+        let result = verifier(stream, policy.as_ref(), &self.limits, &self.rendering).await;
+        self.counters.record(&result);
+        (self.on_outcome)(VerificationOutcome { transport, result }).await
+    }
+}
+
+/// Builder for [`VerifierService`].
+pub struct VerifierServiceBuilder {
+    policy_source: Arc<dyn PolicySource>,
+    limits: Limits,
+    rendering: Rendering,
+    nonce: Option<NonceLocation>,
+    on_outcome: OutcomeHandler,
+}
+
+impl VerifierServiceBuilder {
+    /// Sets the limits on the data a prover may exchange with the server.
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Sets how the revealed data is rendered in [`VerifiedData`].
+    ///
+    /// By default, redacted bytes are rendered as `🙈`.
+    pub fn rendering(mut self, rendering: Rendering) -> Self {
+        self.rendering = rendering;
+        self
+    }
+
+    /// Requires provers to put a nonce issued for the session at `location` of their first
+    /// request, so the session is bound to this upgrade request and cannot be reused.
+    ///
+    /// The nonce is sent in the [`NONCE_HEADER`] header of the upgrade response.
+    pub fn nonce(mut self, location: NonceLocation) -> Self {
+        self.nonce = Some(location);
+        self
+    }
+
+    /// Sets the handler called with the outcome of every session.
+    ///
+    /// By default, outcomes are logged.
+    pub fn on_outcome<F, Fut>(mut self, on_outcome: F) -> Self
+    where
+        F: Fn(VerificationOutcome) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.on_outcome = Arc::new(move |outcome| Box::pin(on_outcome(outcome)));
+        self
+    }
+
+    pub fn build(self) -> VerifierService {
+        VerifierService {
+            policy_source: self.policy_source,
+            limits: self.limits,
+            rendering: self.rendering,
+            nonce: self.nonce,
+            on_outcome: self.on_outcome,
+            counters: Arc::default(),
+        }
+    }
+}
+
+/// Default outcome handler.
+async fn log_outcome(outcome: VerificationOutcome) {
+    match outcome.result {
+        Ok(data) if data.verdict.passed => {
+            if data.duplicate {
+                warn!("Session duplicates an earlier one:\n{}", data.verdict);
+            }
+            info!(
+                "Successfully verified {} (connected at {})",
+                data.server_name.as_deref().unwrap_or("unknown server"),
+                httpdate::fmt_http_date(data.connection_time)
+            );
+            info!("Verified sent data:\n{}", data.sent,);
+            println!("Verified received data:\n{}", data.received,);
+        }
+        Ok(data) => {
+            error!(
+                "Verification failed using {}:\n{}",
+                outcome.transport, data.verdict
+            );
+        }
+        Err(err) => {
+            error!("Failed verification using {}: {err}", outcome.transport);
+        }
+    }
+}
+
+/// Upgrades `/verify` to either a WebSocket (`Upgrade: websocket`) or a raw TCP stream
+/// (`Upgrade: tlsn`), so both transports share the same state and checks.
+async fn upgrade_protocol(State(service): State<VerifierService>, request: Request) -> Response {
+    let (mut parts, _) = request.into_parts();
+
+    let mut policy = match service.policy_source.policy().await {
+        Ok(policy) => policy,
+        Err(err) => {
+            error!("Failed to load policy: {err}");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to load policy").into_response();
+        }
+    };
+
+    let nonce = service.nonce.clone().map(|location| {
+        let nonce = format!("{:032x}", rand::random::<u128>());
+        policy = all([policy.clone(), Nonce::new(&nonce, location).boxed()]).boxed();
+        nonce
+    });
+
+    let mut response = if header_eq(&parts.headers, header::UPGRADE, "websocket") {
+        match WebSocketUpgrade::from_request_parts(&mut parts, &service).await {
+            Ok(ws) => ws_handler(ws, service, policy),
+            Err(rejection) => rejection.into_response(),
+        }
+    } else {
+        match TcpUpgrade::from_request_parts(&mut parts, &service).await {
+            Ok(tcp) => tcp_handler(tcp, service, policy),
+            Err(rejection) => rejection,
+        }
+    };
+
+    if let Some(nonce) = nonce {
+        if response.status() == StatusCode::SWITCHING_PROTOCOLS {
+            let value = HeaderValue::from_str(&nonce).expect("hex is a valid header value");
+            response.headers_mut().insert(NONCE_HEADER, value);
+        }
+    }
+    response
+}
+
+fn ws_handler(
+    ws: WebSocketUpgrade,
+    service: VerifierService,
+    policy: Arc<dyn TranscriptPolicy>,
+) -> Response {
+    info!("Received websocket request");
+    let requested_protocols = ws
+        .requested_protocols()
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    let ws = ws.protocols(SUPPORTED_PROTOCOLS.iter().copied());
+
+    match ws.selected_protocol() {
+        Some(protocol) => debug!("Selected protocol: {:?}", protocol),
+        // Browser provers (tlsn-wasm) cannot offer subprotocols, so only refuse provers that
+        // explicitly asked for versions we do not speak.
+        None if requested_protocols.is_empty() => {
+            warn!(
+                "Prover did not offer a protocol version, assuming {}",
+                SUPPORTED_PROTOCOLS[0]
+            );
+        }
+        None => return unsupported_protocol(&requested_protocols),
+    }
+
+    ws.on_upgrade(|socket| handle_socket(socket, service, policy))
+}
+
+fn tcp_handler(
+    tcp: TcpUpgrade,
+    service: VerifierService,
+    policy: Arc<dyn TranscriptPolicy>,
+) -> Response {
+    info!("Received tcp upgrade request");
+    match tcp.requested_protocol() {
+        Some(protocol) if SUPPORTED_PROTOCOLS.contains(&protocol) => {
+            debug!("Selected protocol: {protocol}")
+        }
+        Some(protocol) => return unsupported_protocol(&[protocol]),
+        None => warn!(
+            "Prover did not request a protocol version, assuming {}",
+            SUPPORTED_PROTOCOLS[0]
+        ),
+    }
+
+    tcp.on_upgrade(|stream| async move {
+        debug!("Upgraded to tcp connection");
+        service
+            .handle_verification(stream, policy, Transport::Tcp)
+            .await
+    })
+}
+
+/// Refuses an upgrade, naming the protocol versions this verifier supports.
+fn unsupported_protocol(requested_protocols: &[impl AsRef<str>]) -> Response {
+    let requested_protocols = requested_protocols
+        .iter()
+        .map(AsRef::as_ref)
+        .collect::<Vec<_>>();
+    error!("Unsupported protocol versions: {:?}", requested_protocols);
+    (
+        StatusCode::BAD_REQUEST,
+        format!(
+            "Unsupported protocol version(s): {}. This verifier supports: {}",
+            requested_protocols.join(", "),
+            SUPPORTED_PROTOCOLS.join(", ")
+        ),
+    )
+        .into_response()
+}
+
+async fn handle_socket(
+    socket: WebSocket,
+    service: VerifierService,
+    policy: Arc<dyn TranscriptPolicy>,
+) {
+    debug!("Upgraded to websocket connection");
+    let stream = WsStream::new(socket.into_inner());
+
+    service
+        .handle_verification(stream, policy, Transport::WebSocket)
+        .await
+}
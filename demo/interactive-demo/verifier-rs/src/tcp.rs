@@ -0,0 +1,104 @@
+//! Raw TCP transport for native provers, negotiated with an HTTP `Upgrade: tlsn` request.
+//!
+//! This skips the WebSocket framing used by browser provers: once the upgrade succeeds, the
+//! underlying connection is handed to the verifier as is.
+use async_trait::async_trait;
+use axum::{
+    extract::FromRequestParts,
+    response::{IntoResponse, Response},
+};
+use axum_core::body::Body;
+use http::{
+    header::{self, HeaderValue},
+    request::Parts,
+    StatusCode,
+};
+use hyper::upgrade::{OnUpgrade, Upgraded};
+use hyper_util::rt::TokioIo;
+use std::future::Future;
+use tracing::error;
+
+/// Name of the protocol in the `Upgrade` header, optionally followed by `/<version>`.
+pub const TLSN_UPGRADE: &str = "tlsn";
+
+/// Extractor for upgrading an HTTP/1.1 connection to a raw TCP stream.
+pub struct TcpUpgrade {
+    on_upgrade: OnUpgrade,
+    /// The protocol requested in the `Upgrade` header, e.g. `tlsn/0.1.0-alpha.12`.
+    protocol: String,
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for TcpUpgrade
+where
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let protocol = parts
+            .headers
+            .get(header::UPGRADE)
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+            .filter(|value| value.split('/').next().unwrap_or_default() == TLSN_UPGRADE)
+            .ok_or_else(|| {
+                (
+                    StatusCode::BAD_REQUEST,
+                    format!("`Upgrade` header did not include '{TLSN_UPGRADE}'"),
+                )
+                    .into_response()
+            })?
+            .to_string();
+
+        let on_upgrade = parts.extensions.remove::<OnUpgrade>().ok_or_else(|| {
+            (StatusCode::UPGRADE_REQUIRED, "Connection is not upgradable").into_response()
+        })?;
+
+        Ok(Self {
+            on_upgrade,
+            protocol,
+        })
+    }
+}
+
+impl TcpUpgrade {
+    /// The version requested after `tlsn/` in the `Upgrade` header, if any.
+    pub fn requested_protocol(&self) -> Option<&str> {
+        (self.protocol != TLSN_UPGRADE).then_some(self.protocol.as_str())
+    }
+
+    /// Finalize upgrading the connection and call the provided callback with the raw stream.
+    #[must_use = "to set up the TCP connection, this response must be returned"]
+    pub fn on_upgrade<C, Fut>(self, callback: C) -> Response
+    where
+        C: FnOnce(TokioIo<Upgraded>) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let on_upgrade = self.on_upgrade;
+        tokio::spawn(async move {
+            let upgraded = match on_upgrade.await {
+                Ok(upgraded) => upgraded,
+                Err(err) => {
+                    error!("Something wrong with on_upgrade: {:?}", err);
+                    return;
+                }
+            };
+            callback(TokioIo::new(upgraded)).await;
+        });
+
+        #[allow(clippy::declare_interior_mutable_const)]
+        const UPGRADE: HeaderValue = HeaderValue::from_static("upgrade");
+
+        Response::builder()
+            .status(StatusCode::SWITCHING_PROTOCOLS)
+            .header(header::CONNECTION, UPGRADE)
+            .header(
+                header::UPGRADE,
+                HeaderValue::from_str(&self.protocol)
+                    .expect("header value was parsed from a request"),
+            )
+            .body(Body::empty())
+            .unwrap()
+    }
+}
@@ -0,0 +1,152 @@
+//! Redaction-aware views over the transcript revealed by the prover.
+use std::ops::Range;
+use tlsn_core::transcript::PartialTranscript;
+
+/// One direction of a partially revealed transcript.
+///
+/// Bytes outside the authenticated ranges are redacted; their value in `data` is meaningless.
+#[derive(Clone, Debug)]
+pub struct Revealed<'a> {
+    data: &'a [u8],
+    /// Sorted, non-overlapping and non-adjacent authenticated ranges.
+    authed: Vec<Range<usize>>,
+}
+
+impl<'a> Revealed<'a> {
+    pub fn new(data: &'a [u8], authed: impl IntoIterator<Item = Range<usize>>) -> Self {
+        let mut ranges = authed
+            .into_iter()
+            .map(|range| range.start.min(data.len())..range.end.min(data.len()))
+            .filter(|range| !range.is_empty())
+            .collect::<Vec<_>>();
+        ranges.sort_by_key(|range| range.start);
+
+        let mut authed: Vec<Range<usize>> = Vec::with_capacity(ranges.len());
+        for range in ranges {
+            match authed.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => authed.push(range),
+            }
+        }
+
+        Self { data, authed }
+    }
+
+    /// Data sent by the prover to the server.
+    pub fn sent(transcript: &'a PartialTranscript) -> Self {
+        Self::new(
+            transcript.sent_unsafe(),
+            transcript.sent_authed().iter_ranges(),
+        )
+    }
+
+    /// Data received by the prover from the server.
+    pub fn received(transcript: &'a PartialTranscript) -> Self {
+        Self::new(
+            transcript.received_unsafe(),
+            transcript.received_authed().iter_ranges(),
+        )
+    }
+
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Authenticated ranges, sorted.
+    pub fn authed(&self) -> &[Range<usize>] {
+        &self.authed
+    }
+
+    /// Redacted ranges, sorted.
+    pub fn redacted(&self) -> Vec<Range<usize>> {
+        let mut redacted = Vec::new();
+        let mut start = 0;
+        for range in &self.authed {
+            if range.start > start {
+                redacted.push(start..range.start);
+            }
+            start = range.end;
+        }
+        if start < self.data.len() {
+            redacted.push(start..self.data.len());
+        }
+        redacted
+    }
+
+    pub fn is_revealed(&self, idx: usize) -> bool {
+        self.authed_range(idx).is_some()
+    }
+
+    /// Whether every byte in `range` is revealed.
+    pub fn is_range_revealed(&self, range: Range<usize>) -> bool {
+        range.is_empty()
+            || self
+                .authed_range(range.start)
+                .is_some_and(|authed| range.end <= authed.end)
+    }
+
+    /// Returns the revealed bytes in `range`, or `None` if any of them is redacted.
+    pub fn get(&self, range: Range<usize>) -> Option<&'a [u8]> {
+        (range.end <= self.data.len() && self.is_range_revealed(range.clone()))
+            .then(|| &self.data[range])
+    }
+
+    /// Finds the first fully revealed occurrence of `needle` at or after `from`.
+    pub fn find(&self, needle: &[u8], from: usize) -> Option<usize> {
+        if needle.is_empty() {
+            return Some(from);
+        }
+        let last = self.data.len().checked_sub(needle.len())?;
+        (from..=last).find(|&idx| {
+            &self.data[idx..idx + needle.len()] == needle
+                && self.is_range_revealed(idx..idx + needle.len())
+        })
+    }
+
+    fn authed_range(&self, idx: usize) -> Option<&Range<usize>> {
+        let pos = self.authed.partition_point(|range| range.end <= idx);
+        self.authed.get(pos).filter(|range| range.contains(&idx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merges_and_complements_ranges() {
+        let data = b"0123456789";
+        let revealed = Revealed::new(data, [5..7, 0..2, 1..3, 7..8, 20..30]);
+
+        assert_eq!(revealed.authed(), &[0..3, 5..8]);
+        assert_eq!(revealed.redacted(), vec![3..5, 8..10]);
+        assert!(revealed.is_range_revealed(5..8));
+        assert!(!revealed.is_range_revealed(2..6));
+        assert_eq!(revealed.get(0..3), Some(&b"012"[..]));
+        assert_eq!(revealed.get(3..4), None);
+    }
+
+    #[test]
+    fn finds_only_revealed_matches() {
+        let data = b"abcabcabc";
+        let revealed = Revealed::new(data, [0..2, 3..9]);
+
+        assert_eq!(revealed.find(b"abc", 0), Some(3));
+        assert_eq!(revealed.find(b"abc", 4), Some(6));
+        assert_eq!(revealed.find(b"abcd", 0), None);
+        assert_eq!(revealed.find(b"abc", 7), None);
+
+        // Transcripts shorter than the needle have no match.
+        let short = Revealed::new(b"HTT", std::iter::once(0..3));
+        assert_eq!(short.find(b"\r\n\r\n", 0), None);
+        assert_eq!(short.find(b"HTTP", 0), None);
+    }
+}